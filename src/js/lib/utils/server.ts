@@ -0,0 +1,70 @@
+import { fs, os, path } from "../cep/node";
+
+// Used until the server has written server.json
+const DEFAULT_SERVER_URL = "ws://localhost:7878";
+
+// Protocol revision these panels speak; PROTOCOL_VERSION in protocol.rs
+export const PROTOCOL_VERSION = 1;
+// The server closes with this code after refusing our HELLO; reconnecting won't help
+export const INCOMPATIBLE_CLOSE_CODE = 1002;
+
+export type ClientRole = "MAIN_PANEL" | "SETTINGS_PANEL";
+
+// The per-user directory with config.json, auth_token and server.json; mirrors default_config_path in paths.rs
+const configDir = () => {
+  const platform = os.platform();
+  if (platform === "win32") {
+    return path.join(
+      process.env.APPDATA || path.join(os.homedir(), "AppData", "Roaming"),
+      "AudioImporter"
+    );
+  }
+  if (platform === "darwin") {
+    return path.join(os.homedir(), "Library", "Application Support", "AudioImporter");
+  }
+  const xdg = process.env.XDG_CONFIG_HOME;
+  return path.join(
+    xdg && path.isAbsolute(xdg) ? xdg : path.join(os.homedir(), ".config"),
+    "AudioImporter"
+  );
+};
+
+// What the running server wrote to server.json: where it listens and where its token is
+interface Discovery {
+  url?: string;
+  tokenFile?: string;
+  // The HTTP API, for scripts; absent when it is disabled
+  httpUrl?: string;
+}
+
+const readDiscovery = (): Discovery => {
+  try {
+    return JSON.parse(fs.readFileSync(path.join(configDir(), "server.json"), "utf8")) || {};
+  } catch {
+    return {};
+  }
+};
+
+// The server's per-install secret, or null if it hasn't run yet
+export const readAuthToken = (
+  tokenFile = readDiscovery().tokenFile || path.join(configDir(), "auth_token")
+): string | null => {
+  try {
+    return fs.readFileSync(tokenFile, "utf8").trim() || null;
+  } catch {
+    return null;
+  }
+};
+
+// Address to open the WebSocket on, with the auth token so the server accepts the connection.
+// Read again on every (re)connect, since the server may come back on another port.
+export const serverUrl = (): string => {
+  const discovery = readDiscovery();
+  const url = discovery.url || DEFAULT_SERVER_URL;
+  const token = readAuthToken(discovery.tokenFile || path.join(configDir(), "auth_token"));
+  return token ? `${url}/?token=${encodeURIComponent(token)}` : url;
+};
+
+// First message on every connection: which panel this is and the protocol it speaks
+export const helloMessage = (role: ClientRole): string =>
+  `HELLO:${JSON.stringify({ protocol: PROTOCOL_VERSION, role, client: "audio-importer-panel" })}`;
@@ -0,0 +1,123 @@
+//! Who may talk to the server: the per-install secret a client must present before the server
+//! acts on anything it sends, so a web page or another local program can't rewrite profiles
+//! through `127.0.0.1`, and the origins a browser-based client may connect from. The secret
+//! lives in `auth_token` next to `config.json`, where the panels read it from.
+
+use crate::paths::get_config_path;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+const TOKEN_FILE_NAME: &str = "auth_token";
+
+pub fn token_path() -> PathBuf {
+    get_config_path().with_file_name(TOKEN_FILE_NAME)
+}
+
+/// The current token, creating one on first run.
+pub fn load_or_create_token() -> io::Result<String> {
+    match fs::read_to_string(token_path()) {
+        Ok(token) if !token.trim().is_empty() => Ok(token.trim().to_string()),
+        Ok(_) => rotate_token(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => rotate_token(),
+        Err(e) => Err(e),
+    }
+}
+
+/// Replaces the token with a new random one. A running server keeps accepting the old one until
+/// it is restarted.
+pub fn rotate_token() -> io::Result<String> {
+    // Two v4 UUIDs: 244 random bits from the OS generator
+    let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    write_private(&token_path(), &token)?;
+    Ok(token)
+}
+
+#[cfg(unix)]
+fn write_private(path: &std::path::Path, token: &str) -> io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut file = fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)?;
+    file.write_all(token.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &std::path::Path, token: &str) -> io::Result<()> {
+    // The per-user config directory is already private to the user on Windows
+    fs::write(path, token)
+}
+
+/// Whether `given` is `expected`, the token loaded at startup. Nothing matches an empty token.
+pub fn is_valid(expected: &str, given: &str) -> bool {
+    !expected.is_empty() && constant_time_eq(expected.as_bytes(), given.trim().as_bytes())
+}
+
+/// The `token` parameter of a URL query string such as `token=abc&x=1`.
+pub fn query_token(query: Option<&str>) -> Option<&str> {
+    query?.split('&').find_map(|pair| pair.strip_prefix("token="))
+}
+
+/// Whether a WebSocket handshake's `Origin` header (`None` when absent, as from native clients)
+/// is one of `allowed`. Case and a trailing slash don't matter.
+pub fn origin_allowed(origin: Option<&str>, allowed: &[String]) -> bool {
+    let Some(origin) = origin else { return true };
+    let origin = origin.trim_end_matches('/');
+    allowed.iter().any(|allowed| allowed.trim_end_matches('/').eq_ignore_ascii_case(origin))
+}
+
+/// Compares without stopping at the first difference, so timing doesn't leak how much matched.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn origins(origins: &[&str]) -> Vec<String> {
+        origins.iter().map(|origin| origin.to_string()).collect()
+    }
+
+    #[test]
+    fn native_clients_without_an_origin_are_allowed() {
+        assert!(origin_allowed(None, &[]));
+    }
+
+    #[test]
+    fn only_listed_origins_are_allowed() {
+        let allowed = origins(&["file://", "http://localhost:3000/"]);
+        assert!(origin_allowed(Some("file://"), &allowed));
+        assert!(origin_allowed(Some("HTTP://LOCALHOST:3000"), &allowed));
+        assert!(origin_allowed(Some("http://localhost:3000/"), &allowed));
+        assert!(!origin_allowed(Some("http://localhost:3001"), &allowed));
+        assert!(!origin_allowed(Some("https://evil.example"), &allowed));
+        assert!(!origin_allowed(Some("null"), &allowed));
+        assert!(!origin_allowed(Some("file://"), &[]));
+    }
+
+    #[test]
+    fn token_is_read_from_the_query_string() {
+        assert_eq!(query_token(Some("token=abc")), Some("abc"));
+        assert_eq!(query_token(Some("x=1&token=abc&y=2")), Some("abc"));
+        assert_eq!(query_token(Some("mytoken=abc")), None);
+        assert_eq!(query_token(None), None);
+    }
+
+    #[test]
+    fn comparison_needs_every_byte() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+    }
+
+    #[test]
+    fn only_the_loaded_token_is_valid() {
+        assert!(is_valid("secret", "secret"));
+        assert!(is_valid("secret", " secret\n"));
+        assert!(!is_valid("secret", "other"));
+        assert!(!is_valid("", ""));
+        assert!(!is_valid("", " "));
+    }
+}
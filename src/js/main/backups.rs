@@ -0,0 +1,196 @@
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How many timestamped copies of `config.json` are kept in the backups directory.
+pub const MAX_BACKUPS: usize = 10;
+
+const BACKUP_PREFIX: &str = "config-";
+const BACKUP_SUFFIX: &str = ".json";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupInfo {
+    pub name: String,
+    /// Milliseconds since the Unix epoch at which the backup was taken.
+    pub timestamp: u64,
+    pub size: u64,
+}
+
+pub fn backups_dir(config_path: &Path) -> PathBuf {
+    config_path.parent().unwrap().join("backups")
+}
+
+/// Copies the current `config.json` into the backups directory and drops the oldest copies.
+/// Nothing is copied when the newest backup already holds the same bytes.
+pub fn create_backup(config_path: &Path) -> io::Result<Option<PathBuf>> {
+    create_backup_at(config_path, now_millis())
+}
+
+fn create_backup_at(config_path: &Path, mut timestamp: u64) -> io::Result<Option<PathBuf>> {
+    if !config_path.exists() {
+        return Ok(None);
+    }
+
+    let dir = backups_dir(config_path);
+    fs::create_dir_all(&dir)?;
+
+    let newest = list_backups(config_path)?.into_iter().next();
+    if let Some(newest) = newest {
+        if fs::read(dir.join(newest.name)).ok() == Some(fs::read(config_path)?) {
+            return Ok(None);
+        }
+    }
+
+    let mut backup_path = dir.join(backup_name(timestamp));
+    // Two saves in the same millisecond must not overwrite each other
+    while backup_path.exists() {
+        timestamp += 1;
+        backup_path = dir.join(backup_name(timestamp));
+    }
+    fs::copy(config_path, &backup_path)?;
+
+    for stale in list_backups(config_path)?.into_iter().skip(MAX_BACKUPS) {
+        let _ = fs::remove_file(dir.join(stale.name));
+    }
+
+    Ok(Some(backup_path))
+}
+
+/// Backups found on disk, newest first.
+pub fn list_backups(config_path: &Path) -> io::Result<Vec<BackupInfo>> {
+    let dir = backups_dir(config_path);
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut backups = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if let Some(timestamp) = parse_backup_name(&name) {
+            backups.push(BackupInfo { name, timestamp, size: entry.metadata()?.len() });
+        }
+    }
+
+    backups.sort_by_key(|b| std::cmp::Reverse(b.timestamp));
+    Ok(backups)
+}
+
+/// Resolves a backup name sent by a client, refusing anything that is not one of our backup files.
+pub fn backup_path(config_path: &Path, name: &str) -> Option<PathBuf> {
+    parse_backup_name(name)?;
+    let path = backups_dir(config_path).join(name);
+    path.is_file().then_some(path)
+}
+
+fn backup_name(timestamp: u64) -> String {
+    format!("{}{}{}", BACKUP_PREFIX, timestamp, BACKUP_SUFFIX)
+}
+
+fn parse_backup_name(name: &str) -> Option<u64> {
+    name.strip_prefix(BACKUP_PREFIX)?
+        .strip_suffix(BACKUP_SUFFIX)?
+        .parse()
+        .ok()
+}
+
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A config directory of its own in the temp directory, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("audio_importer_backups_{}_{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+
+        fn config_path(&self) -> PathBuf {
+            self.0.join("config.json")
+        }
+
+        fn save(&self, contents: &str) {
+            fs::write(self.config_path(), contents).unwrap();
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn names(config_path: &Path) -> Vec<String> {
+        list_backups(config_path).unwrap().into_iter().map(|backup| backup.name).collect()
+    }
+
+    #[test]
+    fn nothing_to_back_up_before_the_first_save() {
+        let dir = TempDir::new("first");
+        assert_eq!(create_backup(&dir.config_path()).unwrap(), None);
+        assert!(names(&dir.config_path()).is_empty());
+    }
+
+    #[test]
+    fn only_the_newest_backups_are_kept() {
+        let dir = TempDir::new("rotation");
+        for i in 0..MAX_BACKUPS as u64 + 3 {
+            dir.save(&format!("{{\"save\": {}}}", i));
+            create_backup_at(&dir.config_path(), 1000 + i).unwrap();
+        }
+        let kept = names(&dir.config_path());
+        assert_eq!(kept.len(), MAX_BACKUPS);
+        assert_eq!(kept.first().unwrap(), &backup_name(1000 + MAX_BACKUPS as u64 + 2));
+        assert_eq!(kept.last().unwrap(), &backup_name(1003));
+    }
+
+    #[test]
+    fn backups_in_the_same_millisecond_get_their_own_names() {
+        let dir = TempDir::new("same_millisecond");
+        dir.save("{\"save\": 1}");
+        create_backup_at(&dir.config_path(), 1000).unwrap();
+        dir.save("{\"save\": 2}");
+        let second = create_backup_at(&dir.config_path(), 1000).unwrap().unwrap();
+        assert_eq!(second.file_name().unwrap(), backup_name(1001).as_str());
+        assert_eq!(fs::read_to_string(second).unwrap(), "{\"save\": 2}");
+        assert_eq!(names(&dir.config_path()).len(), 2);
+    }
+
+    #[test]
+    fn unchanged_config_is_not_backed_up_twice() {
+        let dir = TempDir::new("unchanged");
+        dir.save("{\"save\": 1}");
+        assert!(create_backup_at(&dir.config_path(), 1000).unwrap().is_some());
+        assert_eq!(create_backup_at(&dir.config_path(), 2000).unwrap(), None);
+        assert_eq!(names(&dir.config_path()), [backup_name(1000)]);
+    }
+
+    #[test]
+    fn backup_path_only_resolves_our_backups() {
+        let dir = TempDir::new("path");
+        dir.save("{}");
+        create_backup_at(&dir.config_path(), 1000).unwrap();
+        let config_path = dir.config_path();
+        assert_eq!(backup_path(&config_path, "config-1000.json"), Some(backups_dir(&config_path).join("config-1000.json")));
+
+        fs::write(dir.0.join("config-2000.json"), "{}").unwrap();
+        for name in ["../config-2000.json", "config-1000.json/../config-1000.json", "config-../1.json", "config-3000.json", "other.json"] {
+            assert_eq!(backup_path(&config_path, name), None, "{}", name);
+        }
+    }
+}
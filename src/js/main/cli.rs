@@ -0,0 +1,156 @@
+use std::path::PathBuf;
+
+pub const CONFIG_ENV_VAR: &str = "AUDIO_IMPORTER_CONFIG";
+pub const HOST_ENV_VAR: &str = "AUDIO_IMPORTER_HOST";
+pub const PORT_ENV_VAR: &str = "AUDIO_IMPORTER_PORT";
+
+const USAGE: &str = "Usage: audio_importer [--config <path>] [--portable] [--host <address>] [--port <port>] [--input-script <path>] [--midi-file <path>] [--foreground-app <name>] [--rotate-token]
+
+Options:
+  --config <path>   Use this config.json instead of the per-user one
+                    (also settable through AUDIO_IMPORTER_CONFIG)
+  --portable        Keep the config in a `data` folder next to the executable
+  --host <address>  Listen for panels on this address instead of the config's
+                    (also settable through AUDIO_IMPORTER_HOST)
+  --port <port>     Listen for panels on this port instead of the config's
+                    (also settable through AUDIO_IMPORTER_PORT)
+  --input-script <path>
+                    Replay key events from this file instead of listening to the keyboard
+  --midi-file <path>
+                    Replay the notes and controllers of this .mid file instead of listening to MIDI ports
+  --foreground-app <name>
+                    Treat this application as always focused instead of asking the window system
+  --rotate-token    Replace the auth token next to the config with a new one and exit
+  -h, --help        Print this help";
+
+/// Command-line options. Anything not given on the command line falls back to env vars, then defaults.
+#[derive(Debug, Clone, Default)]
+pub struct CliOptions {
+    pub config: Option<PathBuf>,
+    pub portable: bool,
+    /// Override `server.host` / `server.port` from the config.
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    /// Timed key events to replay instead of reading the keyboard, for headless testing.
+    pub input_script: Option<PathBuf>,
+    /// A `.mid` file to replay instead of listening to MIDI controllers.
+    pub midi_file: Option<PathBuf>,
+    /// Stands in for the focused application, for headless testing of `triggerApps`.
+    pub foreground_app: Option<String>,
+    pub rotate_token: bool,
+}
+
+impl CliOptions {
+    pub fn parse() -> Self {
+        match Self::parse_from(std::env::args().skip(1)) {
+            Ok(options) => options,
+            Err(message) => {
+                eprintln!("{}\n\n{}", message, USAGE);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    pub fn parse_from(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = CliOptions::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            // Accept both `--flag value` and `--flag=value`
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let mut value = |name: &str| {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("Missing value for {}", name))
+            };
+
+            match flag.as_str() {
+                "--config" => options.config = Some(PathBuf::from(value("--config")?)),
+                "--portable" => options.portable = true,
+                "--host" => options.host = Some(value("--host")?),
+                "--port" => options.port = Some(parse_port(&value("--port")?)?),
+                "--input-script" => options.input_script = Some(PathBuf::from(value("--input-script")?)),
+                "--midi-file" => options.midi_file = Some(PathBuf::from(value("--midi-file")?)),
+                "--foreground-app" => options.foreground_app = Some(value("--foreground-app")?),
+                "--rotate-token" => options.rotate_token = true,
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    std::process::exit(0);
+                }
+                other => return Err(format!("Unknown argument: {}", other)),
+            }
+        }
+
+        if options.config.is_none() {
+            options.config = std::env::var_os(CONFIG_ENV_VAR)
+                .filter(|v| !v.is_empty())
+                .map(PathBuf::from);
+        }
+        if options.host.is_none() {
+            options.host = std::env::var(HOST_ENV_VAR).ok().filter(|v| !v.is_empty());
+        }
+        if options.port.is_none() {
+            if let Some(port) = std::env::var(PORT_ENV_VAR).ok().filter(|v| !v.is_empty()) {
+                options.port = Some(parse_port(&port)?);
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+fn parse_port(value: &str) -> Result<u16, String> {
+    value.parse().map_err(|_| format!("Invalid port: {}", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<CliOptions, String> {
+        CliOptions::parse_from(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn flags_take_separate_or_inline_values() {
+        let options = parse(&[
+            "--config",
+            "/tmp/a.json",
+            "--input-script=/tmp/keys.txt",
+            "--port=9000",
+            "--host",
+            "0.0.0.0",
+            "--foreground-app",
+            "Adobe Premiere Pro",
+            "--portable",
+            "--rotate-token",
+        ])
+        .unwrap();
+        assert_eq!(options.config, Some(PathBuf::from("/tmp/a.json")));
+        assert_eq!(options.input_script, Some(PathBuf::from("/tmp/keys.txt")));
+        assert_eq!(options.port, Some(9000));
+        assert_eq!(options.host.as_deref(), Some("0.0.0.0"));
+        assert_eq!(options.foreground_app.as_deref(), Some("Adobe Premiere Pro"));
+        assert!(options.portable);
+        assert!(options.rotate_token);
+    }
+
+    #[test]
+    fn inline_value_keeps_later_equals_signs() {
+        let options = parse(&["--config=/tmp/a=b.json", "--midi-file", "/tmp/x.mid"]).unwrap();
+        assert_eq!(options.config, Some(PathBuf::from("/tmp/a=b.json")));
+        assert_eq!(options.midi_file, Some(PathBuf::from("/tmp/x.mid")));
+    }
+
+    #[test]
+    fn mistakes_are_reported() {
+        assert_eq!(parse(&["--config"]).unwrap_err(), "Missing value for --config");
+        assert_eq!(parse(&["--verbose"]).unwrap_err(), "Unknown argument: --verbose");
+        assert_eq!(parse(&["--port", "http"]).unwrap_err(), "Invalid port: http");
+        assert_eq!(parse(&["--port=70000"]).unwrap_err(), "Invalid port: 70000");
+    }
+}
@@ -0,0 +1,279 @@
+use crate::auth;
+use crate::config::{list_backups, read_backup, save_config, Config, ConfigError};
+use crate::conflicts::{check_profile, premiere_shortcuts};
+use crate::premiere::default_shortcuts;
+use crate::protocol::{ClientRole, Command, ErrorCode, NormalizationChange, ProfileName, Reply, TriggerRequest};
+use crate::events::{Event, ServerEvent};
+use crate::layouts::combo_label;
+use crate::state::AppState;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+/// How long `MIDI_LEARN` waits for a control to be pressed.
+const MIDI_LEARN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The connection id of commands that didn't come over a WebSocket (OSC, HTTP). No socket has
+/// it, so every panel hears about their changes.
+pub const NO_CONNECTION: u64 = 0;
+
+/// What the server knows about one connected client.
+#[derive(Debug, Default)]
+pub struct Session {
+    /// Identifies the client on the event channel so it isn't sent its own changes back.
+    pub connection_id: u64,
+    /// Set once the client has completed a `HELLO`; legacy panels never do.
+    pub role: Option<ClientRole>,
+    pub protocol: Option<u32>,
+}
+
+impl Session {
+    pub fn new(connection_id: u64) -> Self {
+        Session { connection_id, ..Session::default() }
+    }
+}
+
+/// Executes one client command against the shared config and tells every other client what changed.
+/// Transport-agnostic: callers render the reply.
+pub async fn handle_command(command: Command, state: &AppState, session: &mut Session) -> Reply {
+    // Callers only get here once authenticated; repeating it just checks the token again
+    if let Command::Auth { token } = &command {
+        return if auth::is_valid(&state.auth_token, token) { Reply::Authenticated } else { Reply::unauthorized() };
+    }
+    if let Command::Status = command {
+        return Reply::Status {
+            input_tasks: state.input.input_tasks(),
+            subscribers: state.input.subscribers(),
+            connections: state.connections.load(Ordering::Relaxed),
+            armed: state.input.armed(),
+        };
+    }
+    if let Command::SetArmed { armed } = command {
+        state.input.set_armed(armed);
+        return Reply::Armed { armed };
+    }
+    if let Command::MidiLearn = command {
+        state.input.start_midi_learn(MIDI_LEARN_TIMEOUT);
+        return Reply::MidiLearning { timeout_ms: MIDI_LEARN_TIMEOUT.as_millis() as u64 };
+    }
+    if let Command::RemoteTrigger(request) = command {
+        return remote_trigger(request, state);
+    }
+
+    let reply = apply_command(command, &state.config, session).await;
+
+    if let Some(event) = Event::from_reply(&reply, &*state.config.lock().await) {
+        // No receivers just means no panel is connected
+        let _ = state.events.send(ServerEvent { origin: Some(session.connection_id), event });
+    }
+    reply
+}
+
+/// Goes through the input hub, which holds the current profile's bindings, rather than the config.
+fn remote_trigger(request: TriggerRequest, state: &AppState) -> Reply {
+    let combo = match (request.combo, request.binding) {
+        (Some(combo), _) => combo,
+        (None, Some(name)) => match state.input.binding_key(&name) {
+            Some(key) => key,
+            None => {
+                return Reply::error(
+                    ErrorCode::BindingNotFound,
+                    format!("No binding called '{}' in the current profile", name),
+                )
+            }
+        },
+        (None, None) => return Reply::error(ErrorCode::BadRequest, "Give either a combo or a binding to trigger"),
+    };
+    let trigger = state.input.inject(&combo);
+    Reply::Triggered { combo, trigger: trigger.map(Box::new) }
+}
+
+async fn apply_command(command: Command, config: &Arc<Mutex<Config>>, session: &mut Session) -> Reply {
+    if let Command::Hello(hello) = command {
+        let reply = Reply::welcome(&hello);
+        if let Reply::Welcome { .. } = reply {
+            session.role = Some(hello.role);
+            session.protocol = Some(hello.protocol);
+            println!(
+                "Handshake: {:?} on protocol {} ({})",
+                hello.role,
+                hello.protocol,
+                hello.client.as_deref().unwrap_or("unknown client")
+            );
+        }
+        return reply;
+    }
+
+    let mut config_guard = config.lock().await;
+
+    match command {
+        Command::Auth { .. }
+        | Command::Hello(_)
+        | Command::Status
+        | Command::SetArmed { .. }
+        | Command::MidiLearn
+        | Command::RemoteTrigger(_) => {
+            unreachable!("handled above")
+        }
+        Command::LoadConfig(None) => Reply::Config(Box::new(config_guard.clone())),
+        Command::LoadConfig(Some(ProfileName { profile })) => match config_guard.profiles.get(&profile) {
+            Some(keybindings) => Reply::ProfileConfig { config: keybindings.clone(), profile },
+            None => Reply::profile_not_found(&profile),
+        },
+
+        Command::SaveConfig(request) => {
+            let profile = request.profile;
+            let saved = mutate_and_save(&mut config_guard, |config| {
+                if let Some(settings) = &request.settings {
+                    // The bindings are keyed by the current policy; switching it needs a migration
+                    if settings.normalization != config.settings_for(&profile).normalization {
+                        return Err(ConfigError::NormalizationMismatch);
+                    }
+                }
+                config.save_profile(&profile, request.config)?;
+                if let Some(settings) = request.settings {
+                    config.profile_settings.insert(profile.clone(), settings);
+                }
+                config.current_profile = Some(profile.clone());
+                config.last_selected_profile = Some(profile.clone());
+                Ok(())
+            });
+            if let Err(e) = saved {
+                return e.into();
+            }
+            println!("Config saved for profile: {}", profile);
+            // A bad shortcut file shouldn't hide the other conflicts; CHECK_CONFLICTS reports it
+            let premiere = premiere_shortcuts(&config_guard).unwrap_or_else(|e| {
+                eprintln!("{}; checking against Premiere's default shortcuts", e);
+                default_shortcuts()
+            });
+            let conflicts = check_profile(&config_guard, &profile, &premiere).unwrap_or_default();
+            Reply::ConfigSaved { profile, conflicts }
+        }
+
+        Command::DeleteProfile { profile } => {
+            mutate_and_save(&mut config_guard, |config| config.delete_profile(&profile))
+                .map_or_else(Reply::from, |_| Reply::ProfileDeleted { profile })
+        }
+
+        Command::CreateProfile { profile } => {
+            mutate_and_save(&mut config_guard, |config| config.create_profile(&profile))
+                .map_or_else(Reply::from, |_| {
+                    println!("Profile created: {}", profile);
+                    Reply::ProfileCreated { profile }
+                })
+        }
+
+        Command::RenameProfile(rename) => {
+            mutate_and_save(&mut config_guard, |config| config.rename_profile(&rename.from, &rename.to))
+                .map_or_else(Reply::from, |_| {
+                    println!("Profile renamed: {} -> {}", rename.from, rename.to);
+                    Reply::ProfileRenamed(rename)
+                })
+        }
+
+        Command::DuplicateProfile(duplicate) => {
+            mutate_and_save(&mut config_guard, |config| config.duplicate_profile(&duplicate.from, &duplicate.to))
+                .map_or_else(Reply::from, |_| Reply::ProfileDuplicated(duplicate))
+        }
+
+        Command::ReorderProfiles { order } => {
+            mutate_and_save(&mut config_guard, |config| config.reorder_profiles(order))
+                .map_or_else(Reply::from, |_| Reply::ProfilesReordered { profiles: config_guard.profile_names() })
+        }
+
+        Command::SwitchProfile { profile } => {
+            let Some(keybindings) = config_guard.profiles.get(&profile).cloned() else {
+                return Reply::profile_not_found(&profile);
+            };
+            mutate_and_save(&mut config_guard, |config| {
+                config.current_profile = Some(profile.clone());
+                config.last_selected_profile = Some(profile.clone());
+                Ok(())
+            })
+            .map_or_else(Reply::from, |_| Reply::ProfileSwitched { profile, config: keybindings })
+        }
+
+        Command::GetProfiles => Reply::Profiles { profiles: config_guard.profile_names() },
+        Command::GetCurrentProfile => Reply::CurrentProfile { profile: config_guard.current_profile.clone() },
+        Command::GetLastSelectedProfile => {
+            Reply::LastSelectedProfile { profile: config_guard.last_selected_profile.clone() }
+        }
+
+        Command::SaveLastSelectedProfile { profile } => {
+            mutate_and_save(&mut config_guard, |config| {
+                config.last_selected_profile = Some(profile.clone());
+                // Also update currentProfile if it exists
+                if config.profiles.contains_key(&profile) {
+                    config.current_profile = Some(profile.clone());
+                }
+                Ok(())
+            })
+            .map_or_else(Reply::from, |_| Reply::LastSelectedProfileSaved { profile })
+        }
+
+        Command::SetNormalization(change) => {
+            let NormalizationChange { profile, policy } = change;
+            mutate_and_save(&mut config_guard, |config| config.set_normalization(&profile, policy.clone()))
+                .map_or_else(Reply::from, |_| {
+                    println!("Normalization changed for profile: {}", profile);
+                    Reply::NormalizationChanged {
+                        config: config_guard.profiles.get(&profile).cloned().unwrap_or_default(),
+                        profile,
+                        policy,
+                    }
+                })
+        }
+
+        Command::GetKeyLabels { combos } => {
+            let layout = config_guard.input.keyboard_layout;
+            let labels = combos
+                .into_iter()
+                .map(|combo| {
+                    let label = combo_label(&combo, layout);
+                    (combo, label)
+                })
+                .collect();
+            Reply::KeyLabels { layout, labels }
+        }
+
+        Command::CheckConflicts(name) => {
+            let Some(profile) = name.map(|name| name.profile).or_else(|| config_guard.current_profile.clone()) else {
+                return Reply::error(ErrorCode::ProfileNotFound, "No profile selected");
+            };
+            premiere_shortcuts(&config_guard)
+                .and_then(|premiere| check_profile(&config_guard, &profile, &premiere))
+                .map_or_else(Reply::from, |conflicts| Reply::Conflicts { profile, conflicts })
+        }
+
+        Command::ListBackups => match list_backups() {
+            Ok(backups) => Reply::Backups { backups },
+            Err(e) => e.into(),
+        },
+
+        Command::RestoreBackup { name } => {
+            mutate_and_save(&mut config_guard, |config| {
+                *config = read_backup(&name)?;
+                Ok(())
+            })
+            .map_or_else(Reply::from, |_| {
+                println!("Restored config from backup: {}", name);
+                Reply::BackupRestored { name, config: Box::new(config_guard.clone()) }
+            })
+        }
+    }
+}
+
+/// Applies `change` to a copy of the config and only commits it once it has been written to disk,
+/// so a failed operation or save leaves memory and disk agreeing.
+fn mutate_and_save(
+    config: &mut Config,
+    change: impl FnOnce(&mut Config) -> Result<(), ConfigError>,
+) -> Result<(), ConfigError> {
+    let mut updated = config.clone();
+    change(&mut updated)?;
+    save_config(&updated)?;
+    *config = updated;
+    Ok(())
+}
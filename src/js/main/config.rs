@@ -0,0 +1,830 @@
+use crate::backups::{self, BackupInfo};
+use crate::keys::MODIFIERS;
+use crate::migrations::{self, CURRENT_SCHEMA_VERSION};
+use crate::paths::get_config_path;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// Premiere clamps clip gain at +15 dB; anything quieter than -96 dB is inaudible.
+pub const MIN_VOLUME_DB: f64 = -96.0;
+pub const MAX_VOLUME_DB: f64 = 15.0;
+// Same limits the panel enforces on the pitch input.
+pub const MIN_PITCH_SEMITONES: f64 = -12.0;
+pub const MAX_PITCH_SEMITONES: f64 = 12.0;
+pub const MAX_PROFILE_NAME_LEN: usize = 64;
+pub const MIN_GESTURE_THRESHOLD_MS: u64 = 50;
+pub const MAX_GESTURE_THRESHOLD_MS: u64 = 5000;
+
+lazy_static! {
+    // Contents of the last config.json this process wrote or loaded, so the watcher can skip our own saves
+    static ref LAST_WRITTEN: Mutex<Option<String>> = Mutex::new(None);
+}
+
+/// A single key combination bound to an audio file, mirroring `AudioBinding` in `types.ts`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioBinding {
+    #[serde(deserialize_with = "number_or_string")]
+    pub volume: f64,
+    #[serde(deserialize_with = "number_or_string")]
+    pub pitch: f64,
+    pub track: String,
+    pub path: String,
+    #[serde(default)]
+    pub import_in_middle: bool,
+    /// Set by the panel while it waits for a file to be picked. A draft may have an empty `path`;
+    /// it is saved but never triggers.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub draft: bool,
+    /// Played instead when the combo is held for at least the profile's `holdMs`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hold: Option<Box<AudioBinding>>,
+    /// Played instead when the combo is tapped twice within the profile's `doubleTapMs`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub double_tap: Option<Box<AudioBinding>>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// Combo (e.g. `Ctrl+1`) to binding.
+pub type ProfileConfig = BTreeMap<String, AudioBinding>;
+
+/// The whole `config.json`, mirroring `Config` in `types.ts`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Config {
+    #[serde(default)]
+    pub schema_version: u64,
+    #[serde(default)]
+    pub current_profile: Option<String>,
+    #[serde(default)]
+    pub last_selected_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, ProfileConfig>,
+    /// Display order of `profiles`; kept in sync with its keys.
+    #[serde(default)]
+    pub profile_order: Vec<String>,
+    #[serde(default)]
+    pub input: InputSettings,
+    /// Per-profile settings; profiles without an entry use the defaults.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profile_settings: BTreeMap<String, ProfileSettings>,
+    /// A `.kys` file exported from Premiere to check bindings against; Premiere's defaults when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub premiere_shortcuts: Option<PathBuf>,
+    #[serde(default)]
+    pub osc: OscSettings,
+    #[serde(default)]
+    pub http: HttpSettings,
+    #[serde(default)]
+    pub server: ServerSettings,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileSettings {
+    #[serde(default)]
+    pub gestures: GestureThresholds,
+    /// Changed through `SET_NORMALIZATION`, which also rewrites the profile's combos.
+    #[serde(default)]
+    pub normalization: NormalizationPolicy,
+}
+
+/// How a profile spells the keys pressed as the combo its bindings are stored under.
+/// The default merges left/right modifiers and the numpad into the number row.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NormalizationPolicy {
+    /// Keep `LeftCtrl` and `RightCtrl` (and the other modifiers' sides) apart instead of writing `Ctrl`.
+    #[serde(default)]
+    pub distinguish_sides: bool,
+    /// Keep `Numpad1`..`Numpad0` apart from `1`..`0`.
+    #[serde(default)]
+    pub separate_numpad: bool,
+    /// Order modifiers are written in, e.g. `["Shift", "Ctrl"]`. Empty means the platform order;
+    /// modifiers not listed go after the listed ones.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modifier_order: Vec<String>,
+}
+
+/// When a press counts as a hold or a double tap. Only bindings with a `hold` or `doubleTap`
+/// sound are classified; everything else fires as a plain tap.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GestureThresholds {
+    /// Minimum time between press and release for a hold.
+    #[serde(default = "default_hold_ms")]
+    pub hold_ms: u64,
+    /// Maximum time from the end of the first tap to the end of the second for a double tap. A tap
+    /// on a combo with a `doubleTap` sound is delayed by this much while waiting for the second one.
+    #[serde(default = "default_double_tap_ms")]
+    pub double_tap_ms: u64,
+}
+
+impl Default for GestureThresholds {
+    fn default() -> Self {
+        GestureThresholds { hold_ms: default_hold_ms(), double_tap_ms: default_double_tap_ms() }
+    }
+}
+
+fn default_hold_ms() -> u64 {
+    500
+}
+
+fn default_double_tap_ms() -> u64 {
+    300
+}
+
+/// How key presses are captured. `backend` and `fireOnPress` are read at startup and take effect on
+/// the next launch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InputSettings {
+    #[serde(default)]
+    pub backend: InputBackend,
+    /// Send a combo as soon as its last non-modifier key goes down instead of waiting for every key to be released.
+    #[serde(default)]
+    pub fire_on_press: bool,
+    /// How long a multi-stroke binding such as `Ctrl+K, 3` waits for its next stroke.
+    #[serde(default = "default_sequence_timeout_ms")]
+    pub sequence_timeout_ms: u64,
+    /// Only changes how keys are labelled for the user; bindings keep the US-QWERTY key names.
+    #[serde(default)]
+    pub keyboard_layout: KeyboardLayout,
+    /// Arms and disarms triggers, e.g. while typing a marker name. Handled by the server, never sent to panels.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arm_combo: Option<String>,
+    /// Applications triggers fire in, matched case-insensitively against the focused window's
+    /// class; any application when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trigger_apps: Vec<String>,
+    #[serde(default)]
+    pub midi: MidiSettings,
+}
+
+impl Default for InputSettings {
+    fn default() -> Self {
+        InputSettings {
+            backend: InputBackend::default(),
+            fire_on_press: false,
+            sequence_timeout_ms: default_sequence_timeout_ms(),
+            keyboard_layout: KeyboardLayout::default(),
+            arm_combo: None,
+            trigger_apps: Vec::new(),
+            midi: MidiSettings::default(),
+        }
+    }
+}
+
+/// MIDI controllers, whose notes and CCs bind like keys (`MIDI:ch1:note36`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MidiSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Only listen to input ports whose name contains this; every port when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<String>,
+    /// Also open a virtual input port named `AudioImporter` that other programs can send to.
+    #[serde(default)]
+    pub virtual_port: bool,
+}
+
+/// The OSC listener for control surfaces such as TouchOSC. Read at startup.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OscSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    /// UDP address to listen on; `0.0.0.0:9000` lets a tablet on the network reach it.
+    #[serde(default = "default_osc_bind")]
+    pub bind: String,
+}
+
+impl Default for OscSettings {
+    fn default() -> Self {
+        OscSettings { enabled: false, bind: default_osc_bind() }
+    }
+}
+
+fn default_osc_bind() -> String {
+    "127.0.0.1:9000".to_string()
+}
+
+/// The WebSocket listener the panels connect to. The address is read at startup; `--host` /
+/// `--port` and their env vars take precedence.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerSettings {
+    #[serde(default = "default_host")]
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// How many ports after `port` to try when it is taken by another program; 0 to give up instead.
+    #[serde(default = "default_port_fallbacks")]
+    pub port_fallbacks: u16,
+    /// `Origin` headers a WebSocket handshake may carry, so web pages can't connect. Native
+    /// clients send none and are always let in.
+    #[serde(default = "default_allowed_origins")]
+    pub allowed_origins: Vec<String>,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        ServerSettings {
+            host: default_host(),
+            port: default_port(),
+            port_fallbacks: default_port_fallbacks(),
+            allowed_origins: default_allowed_origins(),
+        }
+    }
+}
+
+fn default_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_port() -> u16 {
+    7878
+}
+
+fn default_port_fallbacks() -> u16 {
+    10
+}
+
+fn default_allowed_origins() -> Vec<String> {
+    // Only the installed panels. `null` is also what sandboxed frames and `data:` pages send, and
+    // the dev servers from cep.config.ts are opt-in, so both have to be listed by hand
+    vec!["file://".to_string()]
+}
+
+/// The HTTP API for scripts and Stream Deck buttons. Read at startup.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpSettings {
+    #[serde(default = "default_http_enabled")]
+    pub enabled: bool,
+    #[serde(default = "default_http_bind")]
+    pub bind: String,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        HttpSettings { enabled: default_http_enabled(), bind: default_http_bind() }
+    }
+}
+
+fn default_http_enabled() -> bool {
+    true
+}
+
+fn default_http_bind() -> String {
+    "127.0.0.1:7879".to_string()
+}
+
+fn default_sequence_timeout_ms() -> u64 {
+    1000
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum InputBackend {
+    /// Press/release events from the OS through `rdev`.
+    #[default]
+    Rdev,
+    /// Polls `device_query` every 50 ms; fallback where event hooks are unavailable.
+    DeviceQuery,
+}
+
+/// The physical layout of the user's keyboard.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum KeyboardLayout {
+    #[default]
+    Qwerty,
+    Azerty,
+    Qwertz,
+}
+
+/// Payload of a `SAVE_CONFIG` message.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SaveConfigRequest {
+    pub profile: String,
+    pub config: ProfileConfig,
+    /// Left unchanged when absent.
+    #[serde(default)]
+    pub settings: Option<ProfileSettings>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+    NewerSchema { found: u64, supported: u64 },
+    UnknownBackup(String),
+    EmptyProfileName,
+    InvalidProfileName(String),
+    ProfileNotFound(String),
+    ProfileExists(String),
+    InvalidProfileOrder,
+    EmptyCombo,
+    InvalidVolume { combo: String, volume: f64 },
+    InvalidPitch { combo: String, pitch: f64 },
+    EmptyPath { combo: String },
+    InvalidTrack { combo: String, track: String },
+    NestedGesture { combo: String },
+    InvalidModifierOrder(String),
+    ComboCollision { combo: String, first: String, second: String },
+    NormalizationMismatch,
+    InvalidGestureThreshold { name: &'static str, value: u64 },
+    InvalidShortcutFile { path: PathBuf, reason: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "Config I/O error: {}", e),
+            ConfigError::Parse(e) => write!(f, "Invalid config JSON: {}", e),
+            ConfigError::NewerSchema { found, supported } => write!(
+                f,
+                "Config schema v{} was written by a newer AudioImporter (this build supports up to v{}); refusing to downgrade",
+                found, supported
+            ),
+            ConfigError::UnknownBackup(name) => write!(f, "Backup '{}' does not exist", name),
+            ConfigError::EmptyProfileName => write!(f, "Profile name must not be empty"),
+            ConfigError::InvalidProfileName(name) => write!(
+                f,
+                "Profile name '{}' must be at most {} characters without leading/trailing spaces or control characters",
+                name, MAX_PROFILE_NAME_LEN
+            ),
+            ConfigError::ProfileNotFound(name) => write!(f, "Profile '{}' does not exist", name),
+            ConfigError::ProfileExists(name) => write!(f, "Profile '{}' already exists", name),
+            ConfigError::InvalidProfileOrder => {
+                write!(f, "Profile order must list every existing profile exactly once")
+            }
+            ConfigError::EmptyCombo => write!(f, "Key combination must not be empty"),
+            ConfigError::InvalidVolume { combo, volume } => write!(
+                f,
+                "Volume {} dB for '{}' is outside {}..={} dB",
+                volume, combo, MIN_VOLUME_DB, MAX_VOLUME_DB
+            ),
+            ConfigError::InvalidPitch { combo, pitch } => write!(
+                f,
+                "Pitch {} for '{}' is outside {}..={} semitones",
+                pitch, combo, MIN_PITCH_SEMITONES, MAX_PITCH_SEMITONES
+            ),
+            ConfigError::EmptyPath { combo } => write!(f, "No audio file set for '{}'", combo),
+            ConfigError::InvalidTrack { combo, track } => {
+                write!(f, "Track '{}' for '{}' is not a valid audio track (A1, A2, ...)", track, combo)
+            }
+            ConfigError::NestedGesture { combo } => {
+                write!(f, "The hold and double-tap sounds for '{}' can't have gestures of their own", combo)
+            }
+            ConfigError::InvalidModifierOrder(modifier) => write!(
+                f,
+                "Modifier order entry '{}' must be one of {} and listed once",
+                modifier,
+                MODIFIERS.join(", ")
+            ),
+            ConfigError::ComboCollision { combo, first, second } => {
+                write!(f, "'{}' and '{}' would both become '{}'", first, second, combo)
+            }
+            ConfigError::NormalizationMismatch => {
+                write!(f, "Normalization can only be changed with SET_NORMALIZATION, which migrates the bindings")
+            }
+            ConfigError::InvalidGestureThreshold { name, value } => write!(
+                f,
+                "{} of {} ms is outside {}..={} ms",
+                name, value, MIN_GESTURE_THRESHOLD_MS, MAX_GESTURE_THRESHOLD_MS
+            ),
+            ConfigError::InvalidShortcutFile { path, reason } => {
+                write!(f, "Can't read Premiere shortcuts from '{}': {}", path.display(), reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ConfigError {
+    fn from(e: serde_json::Error) -> Self {
+        ConfigError::Parse(e)
+    }
+}
+
+impl AudioBinding {
+    /// Zero-based audio track index parsed from `A1`, `A2`, ... (a bare number is accepted too).
+    pub fn track_index(&self) -> Option<usize> {
+        let digits = self
+            .track
+            .trim()
+            .strip_prefix(['A', 'a'])
+            .unwrap_or(self.track.trim());
+        match digits.parse::<usize>() {
+            Ok(n) if n >= 1 => Some(n - 1),
+            _ => None,
+        }
+    }
+
+    pub fn validate(&self, combo: &str) -> Result<(), ConfigError> {
+        if !(MIN_VOLUME_DB..=MAX_VOLUME_DB).contains(&self.volume) {
+            return Err(ConfigError::InvalidVolume { combo: combo.to_string(), volume: self.volume });
+        }
+        if !(MIN_PITCH_SEMITONES..=MAX_PITCH_SEMITONES).contains(&self.pitch) {
+            return Err(ConfigError::InvalidPitch { combo: combo.to_string(), pitch: self.pitch });
+        }
+        if !self.draft && !self.has_path() {
+            return Err(ConfigError::EmptyPath { combo: combo.to_string() });
+        }
+        if self.track_index().is_none() {
+            return Err(ConfigError::InvalidTrack { combo: combo.to_string(), track: self.track.clone() });
+        }
+        for gesture in self.gestures() {
+            if gesture.gestures().next().is_some() {
+                return Err(ConfigError::NestedGesture { combo: combo.to_string() });
+            }
+            gesture.validate(combo)?;
+        }
+        Ok(())
+    }
+
+    /// Whether a file has been picked yet.
+    pub fn has_path(&self) -> bool {
+        !self.path.trim().is_empty()
+    }
+
+    /// The hold and double-tap sounds that are set.
+    pub fn gestures(&self) -> impl Iterator<Item = &AudioBinding> {
+        self.hold.iter().chain(&self.double_tap).map(|binding| binding.as_ref())
+    }
+}
+
+#[cfg(test)]
+impl AudioBinding {
+    /// A plain binding on A1 at unity gain and pitch.
+    pub fn for_file(path: &str) -> Self {
+        AudioBinding {
+            volume: 0.0,
+            pitch: 0.0,
+            track: "A1".to_string(),
+            path: path.to_string(),
+            import_in_middle: false,
+            draft: false,
+            hold: None,
+            double_tap: None,
+        }
+    }
+}
+
+impl ProfileSettings {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.gestures.validate()?;
+        self.normalization.validate()
+    }
+}
+
+impl GestureThresholds {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for (name, value) in [("holdMs", self.hold_ms), ("doubleTapMs", self.double_tap_ms)] {
+            if !(MIN_GESTURE_THRESHOLD_MS..=MAX_GESTURE_THRESHOLD_MS).contains(&value) {
+                return Err(ConfigError::InvalidGestureThreshold { name, value });
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn validate_profile_name(name: &str) -> Result<(), ConfigError> {
+    if name.trim().is_empty() {
+        return Err(ConfigError::EmptyProfileName);
+    }
+    Ok(())
+}
+
+/// Stricter rules for names created through the profile management commands.
+pub fn validate_new_profile_name(name: &str) -> Result<(), ConfigError> {
+    validate_profile_name(name)?;
+    if name.chars().count() > MAX_PROFILE_NAME_LEN
+        || name.trim() != name
+        || name.chars().any(char::is_control)
+    {
+        return Err(ConfigError::InvalidProfileName(name.to_string()));
+    }
+    Ok(())
+}
+
+pub fn validate_profile(profile: &ProfileConfig) -> Result<(), ConfigError> {
+    for (combo, binding) in profile {
+        // Every stroke of a sequence like `Ctrl+K, 3` needs a key
+        if combo.split(',').any(|stroke| stroke.trim().is_empty()) {
+            return Err(ConfigError::EmptyCombo);
+        }
+        binding.validate(combo)?;
+    }
+    Ok(())
+}
+
+impl SaveConfigRequest {
+    pub fn parse(payload: &str) -> Result<Self, ConfigError> {
+        let request: SaveConfigRequest = serde_json::from_str(payload)?;
+        request.validate()?;
+        Ok(request)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        validate_profile_name(&self.profile)?;
+        validate_profile(&self.config)?;
+        match &self.settings {
+            Some(settings) => settings.validate(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Config {
+    /// Profile names in display order.
+    pub fn profile_names(&self) -> Vec<String> {
+        self.profile_order.clone()
+    }
+
+    // Fill in currentProfile/lastSelectedProfile when they are missing or stale, and keep
+    // profileOrder listing exactly the existing profiles
+    pub(crate) fn normalize(&mut self) {
+        let profiles = &self.profiles;
+        let mut seen = std::collections::HashSet::new();
+        self.profile_order.retain(|name| profiles.contains_key(name) && seen.insert(name.clone()));
+        for name in profiles.keys() {
+            if !seen.contains(name) {
+                self.profile_order.push(name.clone());
+            }
+        }
+
+        let current_is_valid = self
+            .current_profile
+            .as_ref()
+            .is_some_and(|name| self.profiles.contains_key(name));
+        if !current_is_valid {
+            self.current_profile = self.profiles.keys().next().cloned();
+        }
+        if self.last_selected_profile.is_none() {
+            self.last_selected_profile = self.current_profile.clone();
+        }
+        self.profile_settings.retain(|name, _| profiles.contains_key(name));
+    }
+
+    /// Settings of a profile, defaults if it has none.
+    pub fn settings_for(&self, profile: &str) -> ProfileSettings {
+        self.profile_settings.get(profile).cloned().unwrap_or_default()
+    }
+}
+
+// Older panels sent volume/pitch as the raw text of the input field
+fn number_or_string<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NumberOrString {
+        Number(f64),
+        String(String),
+    }
+
+    match NumberOrString::deserialize(deserializer)? {
+        NumberOrString::Number(n) => Ok(n),
+        NumberOrString::String(s) => {
+            let s = s.trim();
+            if s.is_empty() || s == "-" || s == "-." {
+                Ok(0.0)
+            } else {
+                s.parse().map_err(serde::de::Error::custom)
+            }
+        }
+    }
+}
+
+/// Parses and migrates a config file's contents. Returns the config and the schema version it was at.
+fn parse_config(config_data: &str) -> Result<(Config, u64), ConfigError> {
+    let mut raw: Value = serde_json::from_str(config_data)?;
+    let from = migrations::migrate(&mut raw).map_err(|found| ConfigError::NewerSchema {
+        found,
+        supported: CURRENT_SCHEMA_VERSION,
+    })?;
+
+    let mut config: Config = serde_json::from_value(raw)?;
+    config.normalize();
+    Ok((config, from))
+}
+
+/// Parses a config.json that was changed behind our back, with the same checks as `SAVE_CONFIG`.
+pub fn parse_external_config(config_data: &str) -> Result<Config, ConfigError> {
+    let (config, _) = parse_config(config_data)?;
+    for profile in config.profiles.values() {
+        validate_profile(profile)?;
+    }
+    for settings in config.profile_settings.values() {
+        settings.validate()?;
+    }
+    Ok(config)
+}
+
+pub fn is_own_write(config_data: &str) -> bool {
+    LAST_WRITTEN.lock().unwrap().as_deref() == Some(config_data)
+}
+
+fn remember_write(config_data: &str) {
+    *LAST_WRITTEN.lock().unwrap() = Some(config_data.to_string());
+}
+
+pub fn load_config() -> Result<Config, ConfigError> {
+    let config_path = get_config_path();
+    let existing = fs::read_to_string(&config_path).ok();
+    if let Some(data) = &existing {
+        remember_write(data);
+    }
+    let config_data = existing.clone().unwrap_or_else(|| "{}".to_string());
+
+    let (config, from) = match parse_config(&config_data) {
+        Ok(parsed) => parsed,
+        // A file from a newer build is intact, just not ours to touch
+        Err(e @ ConfigError::NewerSchema { .. }) => return Err(e),
+        Err(e) => {
+            eprintln!("Config file {} is unreadable: {}", config_path.display(), e);
+            let config = load_newest_valid_backup(&config_path).ok_or(e)?;
+
+            // Set the broken file aside instead of overwriting it, then put the backup in place
+            let corrupt_path = config_path.with_extension(format!("corrupt-{}.json", backups::now_millis()));
+            fs::rename(&config_path, &corrupt_path)?;
+            eprintln!("Moved unreadable config to {}", corrupt_path.display());
+            save_config(&config)?;
+            return Ok(config);
+        }
+    };
+
+    if from < CURRENT_SCHEMA_VERSION {
+        // Keep the pre-migration file around in case an upgrade step goes wrong
+        if let Some(original) = existing {
+            let backup_path = config_path.with_extension(format!("v{}.bak", from));
+            fs::write(&backup_path, original)?;
+            println!("Backed up config to {}", backup_path.display());
+        }
+        save_config(&config)?;
+    }
+
+    Ok(config)
+}
+
+fn load_newest_valid_backup(config_path: &Path) -> Option<Config> {
+    let backups = backups::list_backups(config_path).ok()?;
+    for backup in backups {
+        let path = backups::backups_dir(config_path).join(&backup.name);
+        let Ok(data) = fs::read_to_string(&path) else { continue };
+        match parse_config(&data) {
+            Ok((config, _)) => {
+                println!("Recovered config from backup {}", backup.name);
+                return Some(config);
+            }
+            Err(e) => eprintln!("Skipping backup {}: {}", backup.name, e),
+        }
+    }
+    None
+}
+
+/// Loads the named backup with the same checks as a config edited by hand. Saving it makes it
+/// the current config, which backs up the one it replaces.
+pub fn read_backup(name: &str) -> Result<Config, ConfigError> {
+    let config_path = get_config_path();
+    let path = backups::backup_path(&config_path, name)
+        .ok_or_else(|| ConfigError::UnknownBackup(name.to_string()))?;
+
+    parse_external_config(&fs::read_to_string(path)?)
+}
+
+pub fn list_backups() -> Result<Vec<BackupInfo>, ConfigError> {
+    Ok(backups::list_backups(&get_config_path())?)
+}
+
+/// Writes `config.json` through a temp file and a rename so a crash never leaves a truncated file behind.
+pub fn save_config(config: &Config) -> Result<(), ConfigError> {
+    let config_path = get_config_path();
+    let mut config = config.clone();
+    config.schema_version = CURRENT_SCHEMA_VERSION;
+    let config_data = serde_json::to_string_pretty(&config)?;
+
+    let tmp_path = config_path.with_extension("json.tmp");
+    {
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(config_data.as_bytes())?;
+        file.sync_all()?;
+    }
+
+    if !only_selection_changed(&config_path, &config) {
+        backups::create_backup(&config_path)?;
+    }
+    // Record before the rename so the watcher never sees this file as an external edit
+    remember_write(&config_data);
+    fs::rename(&tmp_path, &config_path)?;
+    Ok(())
+}
+
+/// Whether `config` differs from the file at `config_path` only in which profile is selected. That
+/// isn't worth one of the few backups: a Stream Deck flipping profiles would push out every real one.
+fn only_selection_changed(config_path: &Path, config: &Config) -> bool {
+    let Some((on_disk, _)) = fs::read_to_string(config_path).ok().and_then(|data| parse_config(&data).ok()) else {
+        return false;
+    };
+    let reselected = Config {
+        current_profile: on_disk.current_profile.clone(),
+        last_selected_profile: on_disk.last_selected_profile.clone(),
+        ..config.clone()
+    };
+    reselected == on_disk
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switching_profiles_alone_is_only_a_selection_change() {
+        let path = std::env::temp_dir().join(format!("audio_importer_selection_{}.json", std::process::id()));
+        let mut config = Config::default();
+        config.create_profile("Vlog").unwrap();
+        config.create_profile("Podcast").unwrap();
+        config.schema_version = CURRENT_SCHEMA_VERSION;
+        assert!(!only_selection_changed(&path, &config));
+        fs::write(&path, serde_json::to_string_pretty(&config).unwrap()).unwrap();
+
+        let mut switched = config.clone();
+        switched.current_profile = Some("Vlog".to_string());
+        switched.last_selected_profile = Some("Vlog".to_string());
+        assert!(only_selection_changed(&path, &switched));
+
+        switched.upsert_profile("Vlog", [("Ctrl+1".to_string(), AudioBinding::for_file("/sfx/a.wav"))].into());
+        assert!(!only_selection_changed(&path, &switched));
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn only_installed_panels_may_connect_by_default() {
+        assert_eq!(Config::default().server.allowed_origins, ["file://"]);
+    }
+
+    #[test]
+    fn draft_binding_without_a_file_round_trips() {
+        // What the panel sends right after a combo is recorded, before a file is picked
+        let payload = r#"{"profile": "Default", "config": {
+            "Ctrl+1": {"volume": 0, "pitch": 0, "track": "A1", "path": "", "importInMiddle": false, "draft": true},
+            "Ctrl+2": {"volume": -6, "pitch": 2, "track": "A2", "path": "/sfx/whoosh.wav"}
+        }}"#;
+        let request = SaveConfigRequest::parse(payload).expect("drafts are accepted");
+
+        let mut config = Config::default();
+        config.upsert_profile(&request.profile, request.config.clone());
+        config.normalize();
+        config.schema_version = CURRENT_SCHEMA_VERSION;
+        let saved = serde_json::to_string_pretty(&config).unwrap();
+
+        let loaded = parse_external_config(&saved).expect("a saved draft loads back");
+        assert_eq!(loaded, config);
+        assert_eq!(loaded.profiles["Default"], request.config);
+        assert!(loaded.profiles["Default"]["Ctrl+1"].draft);
+        assert!(!loaded.profiles["Default"]["Ctrl+2"].draft);
+        assert!(!saved.contains("\"draft\": false"));
+    }
+
+    #[test]
+    fn finished_binding_needs_a_file() {
+        let payload = r#"{"profile": "Default", "config": {
+            "Ctrl+1": {"volume": 0, "pitch": 0, "track": "A1", "path": " "}
+        }}"#;
+        assert!(matches!(SaveConfigRequest::parse(payload), Err(ConfigError::EmptyPath { .. })));
+        assert!(matches!(
+            AudioBinding::for_file("").validate("Ctrl+1"),
+            Err(ConfigError::EmptyPath { combo }) if combo == "Ctrl+1"
+        ));
+    }
+
+    #[test]
+    fn draft_binding_is_still_validated_otherwise() {
+        let payload = r#"{"profile": "Default", "config": {
+            "Ctrl+1": {"volume": 0, "pitch": 0, "track": "B1", "path": "", "draft": true}
+        }}"#;
+        assert!(matches!(SaveConfigRequest::parse(payload), Err(ConfigError::InvalidTrack { .. })));
+    }
+}
@@ -0,0 +1,195 @@
+//! Bindings whose combo would also do something else: run a Premiere command, trigger an OS
+//! shortcut, or fire another binding of the same profile.
+
+use crate::config::{Config, ConfigError, NormalizationPolicy, ProfileConfig};
+use crate::premiere::{self, PremiereShortcut};
+use serde::Serialize;
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConflictKind {
+    Premiere,
+    System,
+    Binding,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Conflict {
+    /// The binding key as stored in the profile.
+    pub combo: String,
+    /// The stroke of `combo` that collides; `combo` itself unless it is a sequence.
+    pub stroke: String,
+    pub kind: ConflictKind,
+    /// The Premiere command, OS action or other binding key it collides with.
+    pub with: String,
+}
+
+const WINDOWS_SHORTCUTS: &[(&str, &str)] = &[
+    ("Alt+Tab", "Switch windows"),
+    ("Alt+F4", "Close window"),
+    ("Alt+Esc", "Cycle windows"),
+    ("Ctrl+Esc", "Start menu"),
+    ("Ctrl+Alt+Delete", "Security screen"),
+    ("Ctrl+Shift+Esc", "Task Manager"),
+    ("Win+D", "Show desktop"),
+    ("Win+E", "File Explorer"),
+    ("Win+L", "Lock"),
+    ("Win+R", "Run"),
+    ("Win+Tab", "Task view"),
+    ("Shift+Win+S", "Screenshot"),
+];
+
+const MACOS_SHORTCUTS: &[(&str, &str)] = &[
+    ("Cmd+Tab", "Switch apps"),
+    ("Cmd+Space", "Spotlight"),
+    ("Cmd+H", "Hide app"),
+    ("Cmd+M", "Minimize window"),
+    ("Alt+Cmd+Esc", "Force Quit"),
+    ("Shift+Cmd+3", "Screenshot"),
+    ("Shift+Cmd+4", "Screenshot selection"),
+    ("Shift+Cmd+5", "Screenshot tools"),
+    ("Ctrl+Up", "Mission Control"),
+    ("Ctrl+Down", "Application windows"),
+    ("Ctrl+Left", "Previous space"),
+    ("Ctrl+Right", "Next space"),
+];
+
+const LINUX_SHORTCUTS: &[(&str, &str)] = &[
+    ("Alt+Tab", "Switch windows"),
+    ("Alt+F4", "Close window"),
+    ("Ctrl+Alt+Delete", "Log out"),
+    ("Ctrl+Alt+Left", "Previous workspace"),
+    ("Ctrl+Alt+Right", "Next workspace"),
+    ("Ctrl+Alt+T", "Terminal"),
+    ("Win+L", "Lock"),
+];
+
+fn system_shortcuts() -> &'static [(&'static str, &'static str)] {
+    if cfg!(target_os = "macos") {
+        MACOS_SHORTCUTS
+    } else if cfg!(windows) {
+        WINDOWS_SHORTCUTS
+    } else {
+        LINUX_SHORTCUTS
+    }
+}
+
+/// The Premiere shortcuts to check against: the configured `.kys` file, or Premiere's defaults.
+pub fn premiere_shortcuts(config: &Config) -> Result<Vec<PremiereShortcut>, ConfigError> {
+    match &config.premiere_shortcuts {
+        Some(path) => premiere::load_kys(path),
+        None => Ok(premiere::default_shortcuts()),
+    }
+}
+
+/// Checks `profile`, which must exist, against `premiere` shortcuts, the OS and itself.
+pub fn check_profile(
+    config: &Config,
+    profile: &str,
+    premiere: &[PremiereShortcut],
+) -> Result<Vec<Conflict>, ConfigError> {
+    let bindings = config.profiles.get(profile).ok_or_else(|| ConfigError::ProfileNotFound(profile.to_string()))?;
+    Ok(find_conflicts(bindings, &config.settings_for(profile).normalization, premiere))
+}
+
+pub fn find_conflicts(
+    bindings: &ProfileConfig,
+    policy: &NormalizationPolicy,
+    premiere: &[PremiereShortcut],
+) -> Vec<Conflict> {
+    // Premiere and the OS don't care which Ctrl is held, but do tell the numpad apart
+    let merged = NormalizationPolicy { separate_numpad: true, ..NormalizationPolicy::default() };
+    let mut conflicts = Vec::new();
+
+    for combo in bindings.keys() {
+        // Every stroke of a sequence reaches Premiere on its own
+        for stroke in merged.split_sequence(combo) {
+            let premiere_hits = premiere
+                .iter()
+                .filter(|shortcut| merged.normalize(&shortcut.combo) == stroke)
+                .map(|shortcut| (ConflictKind::Premiere, shortcut.command.clone()));
+            let system_hits = system_shortcuts()
+                .iter()
+                .filter(|(shortcut, _)| merged.normalize(shortcut) == stroke)
+                .map(|(_, action)| (ConflictKind::System, action.to_string()));
+
+            conflicts.extend(premiere_hits.chain(system_hits).map(|(kind, with)| Conflict {
+                combo: combo.clone(),
+                stroke: stroke.clone(),
+                kind,
+                with,
+            }));
+        }
+
+        // Two keys that normalize to a common combo both fire on it, e.g. `1+Ctrl` and `Ctrl+1`;
+        // each pair is reported once, under the key that sorts first
+        let fires_on: HashSet<String> = policy.renormalize(combo).into_iter().collect();
+        for other in bindings.keys().filter(|other| *other > combo) {
+            if let Some(shared) = policy.renormalize(other).into_iter().find(|c| fires_on.contains(c)) {
+                conflicts.push(Conflict {
+                    combo: combo.clone(),
+                    stroke: shared,
+                    kind: ConflictKind::Binding,
+                    with: other.clone(),
+                });
+            }
+        }
+    }
+    conflicts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AudioBinding;
+
+    fn bindings(combos: &[&str]) -> ProfileConfig {
+        combos.iter().map(|combo| (combo.to_string(), AudioBinding::for_file("/sfx/a.wav"))).collect()
+    }
+
+    fn binding_conflicts(combos: &[&str]) -> Vec<Conflict> {
+        find_conflicts(&bindings(combos), &NormalizationPolicy::default(), &[])
+            .into_iter()
+            .filter(|conflict| conflict.kind == ConflictKind::Binding)
+            .collect()
+    }
+
+    #[test]
+    fn clashing_bindings_are_reported_once() {
+        assert_eq!(
+            binding_conflicts(&["Ctrl+1", "1+Ctrl", "F5"]),
+            [Conflict {
+                combo: "1+Ctrl".to_string(),
+                stroke: "Ctrl+1".to_string(),
+                kind: ConflictKind::Binding,
+                with: "Ctrl+1".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn every_clashing_pair_is_reported() {
+        let pairs: Vec<(String, String)> = binding_conflicts(&["Ctrl+1", "1+Ctrl", "LeftCtrl+1"])
+            .into_iter()
+            .map(|conflict| (conflict.combo, conflict.with))
+            .collect();
+        assert_eq!(pairs.len(), 3, "{:?}", pairs);
+    }
+
+    #[test]
+    fn premiere_shortcut_is_reported_per_stroke() {
+        let premiere = [PremiereShortcut { command: "Render".to_string(), combo: "Enter".to_string() }];
+        let conflicts = find_conflicts(&bindings(&["Enter"]), &NormalizationPolicy::default(), &premiere);
+        assert_eq!(
+            conflicts,
+            [Conflict {
+                combo: "Enter".to_string(),
+                stroke: "Enter".to_string(),
+                kind: ConflictKind::Premiere,
+                with: "Render".to_string(),
+            }]
+        );
+    }
+}
@@ -0,0 +1,71 @@
+//! `server.json`, which tells the panels and scripts where this server ended up listening (either
+//! listener may have moved past a taken port) and where its auth token is, so neither has to be
+//! hard-coded on their side.
+
+use crate::auth;
+use crate::paths::discovery_path;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::PathBuf;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Discovery {
+    url: String,
+    host: String,
+    port: u16,
+    pid: u32,
+    token_file: PathBuf,
+    /// The HTTP API's base URL; absent when it is disabled or couldn't bind.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    http_url: Option<String>,
+}
+
+/// Announces the WebSocket listener at `addr` and the HTTP API at `http`, replacing whatever an
+/// earlier run left behind.
+pub fn write(addr: SocketAddr, http: Option<SocketAddr>) -> io::Result<PathBuf> {
+    let websocket = reachable(addr);
+    let discovery = Discovery {
+        url: format!("ws://{}", websocket),
+        host: websocket.ip().to_string(),
+        port: addr.port(),
+        pid: std::process::id(),
+        token_file: auth::token_path(),
+        http_url: http.map(|http| format!("http://{}", reachable(http))),
+    };
+
+    let path = discovery_path();
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_string_pretty(&discovery).map_err(io::Error::other)?;
+    fs::write(&path, json)?;
+    Ok(path)
+}
+
+/// Where a client connects to reach `addr`. Clients can't connect to 0.0.0.0; the loopback address
+/// of the same family reaches it too.
+fn reachable(addr: SocketAddr) -> SocketAddr {
+    let host = match addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ip => ip,
+    };
+    SocketAddr::new(host, addr.port())
+}
+
+/// Removes the file on shutdown, unless another instance has since announced itself in it.
+pub fn remove() {
+    let path = discovery_path();
+    let ours = fs::read_to_string(&path)
+        .ok()
+        .and_then(|json| serde_json::from_str::<Discovery>(&json).ok())
+        .is_some_and(|discovery| discovery.pid == std::process::id());
+    if ours {
+        if let Err(e) = fs::remove_file(&path) {
+            eprintln!("Failed to remove {}: {}", path.display(), e);
+        }
+    }
+}
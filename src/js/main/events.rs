@@ -0,0 +1,188 @@
+//! Config mutations pushed to every connected panel so they never show stale profiles.
+
+use crate::config::{Config, ProfileConfig};
+use crate::input::{Gesture, InputEvent};
+use crate::keys::SEQUENCE_SEPARATOR;
+use crate::protocol::Reply;
+use serde::Serialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "payload", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Event {
+    ProfileSaved { profile: String, config: ProfileConfig },
+    ProfileDeleted { profile: String },
+    ProfileSwitched { profile: String, config: ProfileConfig },
+    /// A profile was created, renamed, duplicated or the list was reordered.
+    #[serde(rename_all = "camelCase")]
+    ProfilesChanged { profiles: Vec<String>, current_profile: Option<String> },
+    /// The whole config was replaced (external edit or backup restore).
+    ConfigChanged(Box<Config>),
+}
+
+/// An event on the broadcast channel. `origin` is the connection that caused it, which already got a reply.
+#[derive(Debug, Clone)]
+pub struct ServerEvent {
+    pub origin: Option<u64>,
+    pub event: Event,
+}
+
+impl Event {
+    /// The event a successful command reply implies for everyone else, read from the updated config.
+    pub fn from_reply(reply: &Reply, config: &Config) -> Option<Event> {
+        let event = match reply {
+            Reply::ConfigSaved { profile, .. } => Event::ProfileSaved {
+                profile: profile.clone(),
+                config: config.profiles.get(profile).cloned().unwrap_or_default(),
+            },
+            Reply::ProfileDeleted { profile } => Event::ProfileDeleted { profile: profile.clone() },
+            Reply::ProfileSwitched { profile, config } => {
+                Event::ProfileSwitched { profile: profile.clone(), config: config.clone() }
+            }
+            // Makes the profile current when it exists, which is a switch as far as anyone else can tell
+            Reply::LastSelectedProfileSaved { profile } if config.current_profile.as_ref() == Some(profile) => {
+                Event::ProfileSwitched {
+                    profile: profile.clone(),
+                    config: config.profiles.get(profile).cloned()?,
+                }
+            }
+            Reply::ProfileCreated { .. }
+            | Reply::ProfileRenamed(_)
+            | Reply::ProfileDuplicated(_)
+            | Reply::ProfilesReordered { .. } => Event::ProfilesChanged {
+                profiles: config.profile_names(),
+                current_profile: config.current_profile.clone(),
+            },
+            Reply::BackupRestored { config, .. } => Event::ConfigChanged(config.clone()),
+            // Combos were rewritten and may belong to a profile other than the current one
+            Reply::NormalizationChanged { .. } => Event::ConfigChanged(Box::new(config.clone())),
+            _ => return None,
+        };
+        Some(event)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Failed to serialize event")
+    }
+
+    /// Messages that make an old panel catch up, using only prefixes it already handles
+    /// (plus `CONFIG_CHANGED:` for the full picture).
+    pub fn to_legacy(&self) -> Vec<String> {
+        let switched = |profile: &str, config: &ProfileConfig| {
+            vec![
+                format!("PROFILE_SWITCHED:{}", profile),
+                format!("CONFIG:{}", serde_json::to_string(config).expect("Failed to serialize config")),
+            ]
+        };
+
+        match self {
+            // SAVE_CONFIG also makes the saved profile current
+            Event::ProfileSaved { profile, config } | Event::ProfileSwitched { profile, config } => {
+                switched(profile, config)
+            }
+            Event::ProfileDeleted { profile } => vec![format!("PROFILE_DELETED:{}", profile)],
+            Event::ProfilesChanged { profiles, current_profile } => vec![
+                format!("PROFILES:{}", serde_json::to_string(profiles).expect("Failed to serialize profiles")),
+                format!("CURRENT_PROFILE:{}", current_profile.as_deref().unwrap_or("None")),
+            ],
+            Event::ConfigChanged(config) => {
+                let mut messages = vec![
+                    format!("CONFIG_CHANGED:{}", serde_json::to_string(config).expect("Failed to serialize config")),
+                    format!(
+                        "PROFILES:{}",
+                        serde_json::to_string(&config.profile_names()).expect("Failed to serialize profiles")
+                    ),
+                ];
+                if let Some(profile) = &config.current_profile {
+                    if let Some(bindings) = config.profiles.get(profile) {
+                        messages.extend(switched(profile, bindings));
+                    }
+                }
+                messages
+            }
+        }
+    }
+}
+
+/// A detected key combination for capture in the panel, the binding it triggers, whether triggers
+/// are armed, or the state of a multi-stroke binding in progress. JSON clients also get the press/release times in milliseconds
+/// since the Unix epoch and the label on the configured keyboard layout; holds and double taps
+/// are flagged in both dialects.
+pub fn input_message(event: &InputEvent, json: bool) -> String {
+    match (event, json) {
+        (InputEvent::Combo(combo), true) => serde_json::json!({
+            "type": "COMBO",
+            "payload": {
+                "combo": combo.combo,
+                "pressedAt": unix_millis(combo.pressed_at),
+                "releasedAt": combo.released_at.map(unix_millis),
+                "gesture": combo.gesture,
+                "label": combo.label,
+            }
+        })
+        .to_string(),
+        // Taps keep the plain form old panels already handle
+        (InputEvent::Combo(combo), false) => match combo.gesture {
+            Gesture::Tap => format!("COMBO:{}", combo.combo),
+            Gesture::Hold => format!("COMBO:{}:hold", combo.combo),
+            Gesture::DoubleTap => format!("COMBO:{}:doubleTap", combo.combo),
+        },
+        (InputEvent::SequencePending(pending), true) => {
+            serde_json::json!({ "type": "SEQUENCE_PENDING", "payload": pending }).to_string()
+        }
+        (InputEvent::Trigger(trigger), true) => {
+            serde_json::json!({ "type": "TRIGGER", "payload": trigger }).to_string()
+        }
+        (InputEvent::Trigger(trigger), false) => {
+            format!("TRIGGER:{}", serde_json::to_string(trigger).expect("Failed to serialize trigger"))
+        }
+        (InputEvent::Armed(armed), true) => {
+            serde_json::json!({ "type": "ARMED", "payload": { "armed": armed } }).to_string()
+        }
+        (InputEvent::Armed(armed), false) => format!("ARMED:{}", armed),
+        (InputEvent::MidiLearned(control), true) => {
+            serde_json::json!({ "type": "MIDI_LEARNED", "payload": { "control": control } }).to_string()
+        }
+        (InputEvent::MidiLearned(control), false) => format!("MIDI_LEARNED:{}", control),
+        (InputEvent::SequencePending(pending), false) => {
+            // Only ever displayed, so old panels get the labels
+            format!("SEQUENCE_PENDING:{}", pending.labels.join(SEQUENCE_SEPARATOR))
+        }
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_with(profiles: &[&str], current: &str) -> Config {
+        let mut config = Config::default();
+        for profile in profiles {
+            config.upsert_profile(profile, ProfileConfig::new());
+        }
+        config.current_profile = Some(current.to_string());
+        config.normalize();
+        config
+    }
+
+    #[test]
+    fn saving_last_selected_profile_is_a_switch_for_everyone_else() {
+        let config = config_with(&["Default", "Vlog"], "Vlog");
+        let reply = Reply::LastSelectedProfileSaved { profile: "Vlog".to_string() };
+        match Event::from_reply(&reply, &config) {
+            Some(Event::ProfileSwitched { profile, .. }) => assert_eq!(profile, "Vlog"),
+            other => panic!("expected PROFILE_SWITCHED, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn saving_an_unknown_last_selected_profile_changes_nothing_visible() {
+        let config = config_with(&["Default"], "Default");
+        let reply = Reply::LastSelectedProfileSaved { profile: "Gone".to_string() };
+        assert!(Event::from_reply(&reply, &config).is_none());
+    }
+}
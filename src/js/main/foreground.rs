@@ -0,0 +1,47 @@
+//! Which application has the keyboard focus, so triggers can be limited to the apps they are meant for.
+
+#[cfg(target_os = "linux")]
+mod x11;
+
+#[cfg(target_os = "linux")]
+pub use self::x11::X11Foreground;
+
+/// Tells which application is in the foreground.
+pub trait ForegroundProvider: Send + Sync + 'static {
+    fn name(&self) -> &'static str;
+
+    /// The focused application's name (the window class on X11), or `None` when it can't be told.
+    fn foreground_app(&self) -> Option<String>;
+}
+
+/// Always reports the same application. Stands in where the platform has no provider yet, and
+/// for headless runs through `--foreground-app`.
+pub struct FixedForeground(pub Option<String>);
+
+impl ForegroundProvider for FixedForeground {
+    fn name(&self) -> &'static str {
+        "fixed"
+    }
+
+    fn foreground_app(&self) -> Option<String> {
+        self.0.clone()
+    }
+}
+
+/// Picks the provider for this run: an app given on the command line wins over the platform's.
+pub fn provider_for(fixed: Option<&str>) -> Box<dyn ForegroundProvider> {
+    if let Some(app) = fixed {
+        return Box::new(FixedForeground(Some(app.to_string())));
+    }
+    platform_provider()
+}
+
+#[cfg(target_os = "linux")]
+fn platform_provider() -> Box<dyn ForegroundProvider> {
+    Box::new(X11Foreground::new())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn platform_provider() -> Box<dyn ForegroundProvider> {
+    Box::new(FixedForeground(None))
+}
@@ -0,0 +1,88 @@
+use super::ForegroundProvider;
+use std::ffi::CStr;
+use std::os::raw::c_int;
+use std::sync::Once;
+use std::{mem, ptr};
+use ::x11::xlib;
+
+/// Reads the class of `_NET_ACTIVE_WINDOW` from the window manager. Opens its own display
+/// connection per query, so it never shares one with the input backends' threads.
+pub struct X11Foreground;
+
+impl X11Foreground {
+    pub fn new() -> Self {
+        // Xlib's default handler exits the process, e.g. when the window closes mid-query
+        static IGNORE_ERRORS: Once = Once::new();
+        IGNORE_ERRORS.call_once(|| unsafe {
+            xlib::XSetErrorHandler(Some(ignore_error));
+        });
+        X11Foreground
+    }
+}
+
+impl ForegroundProvider for X11Foreground {
+    fn name(&self) -> &'static str {
+        "x11"
+    }
+
+    fn foreground_app(&self) -> Option<String> {
+        unsafe {
+            let display = xlib::XOpenDisplay(ptr::null());
+            if display.is_null() {
+                return None;
+            }
+            let app = active_window(display).and_then(|window| window_class(display, window));
+            xlib::XCloseDisplay(display);
+            app
+        }
+    }
+}
+
+unsafe extern "C" fn ignore_error(_: *mut xlib::Display, _: *mut xlib::XErrorEvent) -> c_int {
+    0
+}
+
+unsafe fn active_window(display: *mut xlib::Display) -> Option<xlib::Window> {
+    let property = xlib::XInternAtom(display, c"_NET_ACTIVE_WINDOW".as_ptr(), xlib::True);
+    if property == 0 {
+        return None;
+    }
+
+    let (mut actual_type, mut format, mut items, mut bytes_after) = (0, 0, 0, 0);
+    let mut data: *mut u8 = ptr::null_mut();
+    let status = xlib::XGetWindowProperty(
+        display,
+        xlib::XDefaultRootWindow(display),
+        property,
+        0,
+        1,
+        xlib::False,
+        xlib::XA_WINDOW,
+        &mut actual_type,
+        &mut format,
+        &mut items,
+        &mut bytes_after,
+        &mut data,
+    );
+    if status != xlib::Success as c_int || data.is_null() {
+        return None;
+    }
+    // 32-bit properties come back as C longs
+    let window = (format == 32 && items == 1).then(|| *(data as *const xlib::Window));
+    xlib::XFree(data.cast());
+    window.filter(|window| *window != 0)
+}
+
+unsafe fn window_class(display: *mut xlib::Display, window: xlib::Window) -> Option<String> {
+    let mut hint: xlib::XClassHint = mem::zeroed();
+    if xlib::XGetClassHint(display, window, &mut hint) == 0 {
+        return None;
+    }
+    let class = (!hint.res_class.is_null()).then(|| CStr::from_ptr(hint.res_class).to_string_lossy().into_owned());
+    for name in [hint.res_name, hint.res_class] {
+        if !name.is_null() {
+            xlib::XFree(name.cast());
+        }
+    }
+    class
+}
@@ -0,0 +1,349 @@
+//! Local HTTP API for automation scripts and Stream Deck "website" buttons. Each route is a
+//! protocol command run through `handle_command`, so it is validated and answered exactly like
+//! the same command over the WebSocket. Bodies are the reply's payload; errors are `{code, message}`.
+//! Everything but `/health` needs the auth token, as `Authorization: Bearer <token>` or `?token=`.
+//!
+//! - `GET /health` (`STATUS`)
+//! - `GET /profiles` (`GET_PROFILES`)
+//! - `GET /profiles/{name}` (`LOAD_CONFIG`)
+//! - `PUT /profiles/{name}` with `{"config": {...}, "settings": {...}}` (`SAVE_CONFIG`)
+//! - `POST /profiles/{name}/activate` (`SWITCH_PROFILE`)
+//! - `POST /trigger` with `{"combo": "Ctrl+1"}` or `{"binding": "whoosh"}` (`REMOTE_TRIGGER`)
+
+use crate::auth;
+use crate::commands::{self, Session, NO_CONNECTION};
+use crate::config::HttpSettings;
+use crate::protocol::{self, ErrorCode, Reply};
+use crate::state::AppState;
+use futures_util::stream;
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use warp::http::StatusCode;
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply as _};
+
+/// Far more than a profile with thousands of bindings needs.
+const MAX_BODY_BYTES: u64 = 1024 * 1024;
+
+/// A command `type` and its payload, or why the request is malformed.
+type Request = Result<(&'static str, Option<Value>), String>;
+
+/// Binds the API, moving on to the next of up to `fallbacks` ports like the WebSocket listener,
+/// and returns where it listens and the server to run. Binding happens here, before the WebSocket
+/// listener, so the WebSocket's port fallback steps over our port instead of taking it.
+/// Can't-bind is logged, not fatal: the panels still work.
+pub async fn bind(
+    settings: HttpSettings,
+    fallbacks: u16,
+    state: AppState,
+) -> Option<(SocketAddr, impl Future<Output = ()>)> {
+    let addr = match tokio::net::lookup_host(&settings.bind).await.map(|mut addrs| addrs.next()) {
+        Ok(Some(addr)) => addr,
+        Ok(None) => {
+            eprintln!("Can't serve the HTTP API on {}: no such address", settings.bind);
+            return None;
+        }
+        Err(e) => {
+            eprintln!("Can't serve the HTTP API on {}: {}", settings.bind, e);
+            return None;
+        }
+    };
+    let listener = match crate::listen(&addr.ip().to_string(), addr.port(), fallbacks).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Can't serve the HTTP API: {}", e);
+            return None;
+        }
+    };
+    let addr = listener.local_addr().ok()?;
+    println!("HTTP API listening on: http://{}", addr);
+    let incoming = stream::unfold(listener, |listener| async move {
+        let accepted = listener.accept().await.map(|(stream, _)| stream);
+        Some((accepted, listener))
+    });
+    Some((addr, warp::serve(routes(state)).run_incoming(incoming)))
+}
+
+fn routes(state: AppState) -> impl Filter<Extract = (Response,), Error = Infallible> + Clone {
+    // Path before method, so an unknown path is a 404 rather than a 405
+    let health = warp::path!("health").and(warp::get()).map(|| Ok(("STATUS", None)));
+    let profiles = warp::path!("profiles").and(warp::get()).map(|| Ok(("GET_PROFILES", None)));
+    let profile = warp::path!("profiles" / String)
+        .and(warp::get())
+        .map(|name: String| profile_payload(&name, json!({})).map(|payload| ("LOAD_CONFIG", Some(payload))));
+    let save = warp::path!("profiles" / String).and(warp::put()).and(json_body()).map(
+        |name: String, body: Result<Value, String>| {
+            body.and_then(|body| profile_payload(&name, body)).map(|payload| ("SAVE_CONFIG", Some(payload)))
+        },
+    );
+    let activate = warp::path!("profiles" / String / "activate")
+        .and(warp::post())
+        .map(|name: String| profile_payload(&name, json!({})).map(|payload| ("SWITCH_PROFILE", Some(payload))));
+    let trigger = warp::path!("trigger")
+        .and(warp::post())
+        .and(json_body())
+        .map(|body: Result<Value, String>| body.map(|body| ("REMOTE_TRIGGER", Some(body))));
+
+    health
+        .or(profiles)
+        .unify()
+        .or(profile)
+        .unify()
+        .or(save)
+        .unify()
+        .or(activate)
+        .unify()
+        .or(trigger)
+        .unify()
+        .and(credentials())
+        .and(warp::any().map(move || state.clone()))
+        .then(run)
+        .recover(rejected)
+        .unify()
+}
+
+async fn run(request: Request, token: Option<String>, state: AppState) -> Response {
+    // Health checks carry nothing worth protecting and monitors shouldn't need the secret
+    let public = matches!(request, Ok(("STATUS", _)));
+    if !public && !token.as_deref().is_some_and(|token| auth::is_valid(&state.auth_token, token)) {
+        eprintln!("HTTP request without a valid auth token refused");
+        return respond(&Reply::unauthorized());
+    }
+    let command = request
+        .map_err(|message| Reply::error(ErrorCode::BadRequest, message))
+        .and_then(|(kind, payload)| protocol::build_command(kind, payload));
+    let reply = match command {
+        Ok(command) => commands::handle_command(command, &state, &mut Session::new(NO_CONNECTION)).await,
+        Err(reply) => reply,
+    };
+    if let Reply::Error { message, .. } = &reply {
+        eprintln!("HTTP request failed: {}", message);
+    }
+    respond(&reply)
+}
+
+/// The token from an `Authorization: Bearer` header, or else a `?token=` query parameter.
+fn credentials() -> impl Filter<Extract = (Option<String>,), Error = Infallible> + Clone {
+    let header = warp::header::optional::<String>("authorization").or(warp::any().map(|| None)).unify();
+    let query = warp::query::raw().map(Some).or(warp::any().map(|| None)).unify();
+    header.and(query).map(|header: Option<String>, query: Option<String>| {
+        header
+            .and_then(|header| header.strip_prefix("Bearer ").map(str::to_string))
+            .or_else(|| auth::query_token(query.as_deref()).map(str::to_string))
+    })
+}
+
+/// The body parsed as JSON; a parse error becomes the reply rather than a rejection so it reads
+/// like the WebSocket's.
+fn json_body() -> impl Filter<Extract = (Result<Value, String>,), Error = Rejection> + Clone {
+    warp::body::content_length_limit(MAX_BODY_BYTES)
+        .and(warp::body::bytes())
+        .map(|body: warp::hyper::body::Bytes| serde_json::from_slice(&body).map_err(|e| format!("Invalid JSON: {}", e)))
+}
+
+/// `body` with the profile named in the URL added to it.
+fn profile_payload(segment: &str, mut body: Value) -> Result<Value, String> {
+    let profile = percent_decode(segment)?;
+    let object = body.as_object_mut().ok_or("Expected a JSON object")?;
+    object.insert("profile".to_string(), Value::String(profile));
+    Ok(body)
+}
+
+/// Profile names may have spaces and accents, which arrive percent-encoded in the path.
+fn percent_decode(segment: &str) -> Result<String, String> {
+    let invalid = || format!("Invalid profile name in URL: {}", segment);
+    let mut bytes = Vec::with_capacity(segment.len());
+    let mut rest = segment.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = tail.get(..2).and_then(|hex| std::str::from_utf8(hex).ok()).ok_or_else(invalid)?;
+            bytes.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| invalid())
+}
+
+fn respond(reply: &Reply) -> Response {
+    let status = match reply {
+        Reply::Error { code, .. } => status_for(*code),
+        _ => StatusCode::OK,
+    };
+    let mut body = serde_json::to_value(reply).expect("Failed to serialize reply");
+    let payload = body.get_mut("payload").map(Value::take).unwrap_or(Value::Null);
+    warp::reply::with_status(warp::reply::json(&payload), status).into_response()
+}
+
+fn status_for(code: ErrorCode) -> StatusCode {
+    match code {
+        ErrorCode::BadRequest
+        | ErrorCode::InvalidConfig
+        | ErrorCode::InvalidProfileName
+        | ErrorCode::IncompatibleProtocol => StatusCode::BAD_REQUEST,
+        ErrorCode::UnknownCommand
+        | ErrorCode::ProfileNotFound
+        | ErrorCode::BackupNotFound
+        | ErrorCode::BindingNotFound => StatusCode::NOT_FOUND,
+        ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+        ErrorCode::ProfileExists => StatusCode::CONFLICT,
+        ErrorCode::StorageError => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Unknown routes and methods, answered in the same `{code, message}` shape as command errors.
+async fn rejected(rejection: Rejection) -> Result<Response, Infallible> {
+    // A rejection combines those of every route tried; the other routes' methods not matching
+    // says nothing about the one whose path and method did
+    let (status, message) = if rejection.is_not_found() {
+        (StatusCode::NOT_FOUND, "No such endpoint")
+    } else if rejection.find::<warp::reject::PayloadTooLarge>().is_some() {
+        (StatusCode::PAYLOAD_TOO_LARGE, "Request body too large")
+    } else if rejection.find::<warp::reject::MethodNotAllowed>().is_some() {
+        (StatusCode::METHOD_NOT_ALLOWED, "Method not allowed on this endpoint")
+    } else {
+        (StatusCode::BAD_REQUEST, "Bad request")
+    };
+    let code = if status == StatusCode::NOT_FOUND { ErrorCode::UnknownCommand } else { ErrorCode::BadRequest };
+    let body = json!({ "code": code, "message": message });
+    Ok(warp::reply::with_status(warp::reply::json(&body), status).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AudioBinding, Config};
+
+    fn state() -> AppState {
+        let mut config = Config::default();
+        config.upsert_profile("Vlog", [("Ctrl+1".to_string(), AudioBinding::for_file("/sfx/whoosh.wav"))].into());
+        config.upsert_profile("Voix off", Default::default());
+        config.normalize();
+        AppState::for_config(config)
+    }
+
+    async fn send(request: warp::test::RequestBuilder) -> (StatusCode, Value) {
+        let response = request.reply(&routes(state())).await;
+        let body = serde_json::from_slice(response.body()).expect("JSON body");
+        (response.status(), body)
+    }
+
+    fn authorized(method: &str, path: &str) -> warp::test::RequestBuilder {
+        warp::test::request().method(method).path(path).header("authorization", "Bearer secret")
+    }
+
+    #[tokio::test]
+    async fn health_needs_no_token() {
+        let (status, body) = send(warp::test::request().path("/health")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["connections"], 0);
+    }
+
+    #[tokio::test]
+    async fn token_comes_from_the_header_or_the_query() {
+        let (status, body) = send(authorized("GET", "/profiles")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["profiles"], json!(["Vlog", "Voix off"]));
+        assert_eq!(send(warp::test::request().path("/profiles?token=secret")).await.0, StatusCode::OK);
+
+        for request in [
+            warp::test::request().path("/profiles"),
+            warp::test::request().path("/profiles?token=wrong"),
+            warp::test::request().path("/profiles").header("authorization", "Bearer wrong"),
+            warp::test::request().path("/profiles").header("authorization", "secret"),
+        ] {
+            let (status, body) = send(request).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+            assert_eq!(body["code"], "UNAUTHORIZED");
+        }
+    }
+
+    #[tokio::test]
+    async fn profile_names_are_percent_decoded() {
+        let (status, body) = send(authorized("GET", "/profiles/Voix%20off")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["profile"], "Voix off");
+
+        let (status, body) = send(authorized("GET", "/profiles/Vlog")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["config"]["Ctrl+1"]["path"], "/sfx/whoosh.wav");
+    }
+
+    #[tokio::test]
+    async fn errors_carry_their_code_and_status() {
+        let (status, body) = send(authorized("GET", "/profiles/Podcast")).await;
+        assert_eq!((status, &body["code"]), (StatusCode::NOT_FOUND, &json!("PROFILE_NOT_FOUND")));
+
+        let (status, body) = send(authorized("POST", "/profiles/Podcast/activate")).await;
+        assert_eq!((status, &body["code"]), (StatusCode::NOT_FOUND, &json!("PROFILE_NOT_FOUND")));
+
+        let (status, body) = send(authorized("PUT", "/profiles/vlog").json(&json!({ "config": {} }))).await;
+        assert_eq!((status, &body["code"]), (StatusCode::CONFLICT, &json!("PROFILE_EXISTS")));
+
+        let (status, body) = send(authorized("POST", "/trigger").json(&json!({}))).await;
+        assert_eq!((status, &body["code"]), (StatusCode::BAD_REQUEST, &json!("BAD_REQUEST")));
+
+        let (status, body) = send(authorized("POST", "/trigger").json(&json!({ "binding": "boom" }))).await;
+        assert_eq!((status, &body["code"]), (StatusCode::NOT_FOUND, &json!("BINDING_NOT_FOUND")));
+    }
+
+    #[tokio::test]
+    async fn trigger_by_combo_fires_the_binding() {
+        let (status, body) = send(authorized("POST", "/trigger").json(&json!({ "combo": "Ctrl+1" }))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["trigger"]["binding"]["path"], "/sfx/whoosh.wav");
+    }
+
+    #[tokio::test]
+    async fn malformed_requests_are_bad_requests() {
+        let (status, body) = send(authorized("PUT", "/profiles/Vlog").body("{not json")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["message"].as_str().unwrap().starts_with("Invalid JSON"));
+
+        let (status, _) = send(authorized("PUT", "/profiles/Vlog").json(&json!(["not", "an", "object"]))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _) = send(authorized("GET", "/profiles/%ZZ")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn oversized_bodies_are_refused() {
+        let body = vec![b' '; MAX_BODY_BYTES as usize + 1];
+        let (status, body) = send(authorized("PUT", "/profiles/Vlog").body(body)).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["code"], "BAD_REQUEST");
+    }
+
+    #[tokio::test]
+    async fn unknown_routes_and_methods_answer_in_the_error_shape() {
+        let (status, body) = send(authorized("GET", "/nope")).await;
+        assert_eq!((status, &body["code"]), (StatusCode::NOT_FOUND, &json!("UNKNOWN_COMMAND")));
+
+        let (status, body) = send(authorized("DELETE", "/profiles")).await;
+        assert_eq!((status, &body["code"]), (StatusCode::METHOD_NOT_ALLOWED, &json!("BAD_REQUEST")));
+    }
+
+    #[test]
+    fn percent_decoding() {
+        assert_eq!(percent_decode("Voix%20off"), Ok("Voix off".to_string()));
+        assert_eq!(percent_decode("%C3%A9t%C3%A9"), Ok("été".to_string()));
+        assert_eq!(percent_decode("100%25"), Ok("100%".to_string()));
+        for invalid in ["%2", "%ZZ", "%FF", "%"] {
+            assert!(percent_decode(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn every_error_code_has_a_status() {
+        assert_eq!(status_for(ErrorCode::InvalidConfig), StatusCode::BAD_REQUEST);
+        assert_eq!(status_for(ErrorCode::BackupNotFound), StatusCode::NOT_FOUND);
+        assert_eq!(status_for(ErrorCode::Unauthorized), StatusCode::UNAUTHORIZED);
+        assert_eq!(status_for(ErrorCode::ProfileExists), StatusCode::CONFLICT);
+        assert_eq!(status_for(ErrorCode::StorageError), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
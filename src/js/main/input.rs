@@ -0,0 +1,867 @@
+//! The one keyboard listener for the whole server. Combos are detected once and fanned out to
+//! every subscribed connection.
+//!
+//! Key events come from an [`InputSource`]: the OS hook (`rdev`), `device_query` polling, or a
+//! script replayed from a file for headless testing. Whatever the source, the same
+//! [`ComboTracker`] turns them into combos.
+
+mod device_state;
+mod gate;
+mod gestures;
+mod midi;
+mod midi_file;
+mod rdev_hook;
+mod scripted;
+mod sequences;
+
+pub use device_state::DeviceStateSource;
+pub use gestures::GestureClassifier;
+pub use midi::{MidiSource, MIDI_PREFIX};
+pub use midi_file::MidiFileSource;
+pub use rdev_hook::RdevSource;
+pub use scripted::ScriptedSource;
+pub use sequences::SequenceMatcher;
+
+use gate::TriggerGate;
+
+use crate::config::{AudioBinding, Config, InputBackend, InputSettings, KeyboardLayout, MidiSettings, NormalizationPolicy};
+use crate::events::ServerEvent;
+use crate::foreground::ForegroundProvider;
+use crate::keys::is_modifier;
+use crate::layouts::combo_label;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::broadcast;
+
+/// A detected key combination with the times its first key went down and its last key came up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyCombo {
+    /// Normalized with the current profile's policy, e.g. `Ctrl+1`.
+    pub combo: String,
+    pub pressed_at: SystemTime,
+    /// `None` when the combo fired on press.
+    pub released_at: Option<SystemTime>,
+    pub gesture: Gesture,
+    /// How `combo` reads on the configured keyboard layout, for display only.
+    pub label: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Gesture {
+    #[default]
+    Tap,
+    Hold,
+    DoubleTap,
+}
+
+/// Strokes typed so far towards a multi-stroke binding. Empty once the sequence fired or gave up.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingSequence {
+    pub strokes: Vec<String>,
+    /// The strokes as they read on the configured keyboard layout.
+    pub labels: Vec<String>,
+    /// Bindings the strokes so far could still complete.
+    pub candidates: Vec<String>,
+    pub timeout_ms: u64,
+}
+
+/// A combo bound in the current profile, resolved against the server's config so panels don't
+/// have to look it up in their own, possibly stale, copy.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Trigger {
+    pub profile: String,
+    /// The binding key as stored in the profile.
+    pub combo: String,
+    pub label: String,
+    pub gesture: Gesture,
+    /// What to play: the `hold` or `doubleTap` binding when that gesture fired.
+    pub binding: AudioBinding,
+}
+
+/// What the hub broadcasts to panels.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputEvent {
+    /// Every detected combo, bound or not, so panels can capture new bindings.
+    Combo(KeyCombo),
+    SequencePending(PendingSequence),
+    /// Follows the `Combo` of a bound combo, unless the gate holds it back.
+    Trigger(Trigger),
+    /// Triggers were armed or disarmed, by the arm combo or a panel.
+    Armed(bool),
+    /// The MIDI control pressed while learning, e.g. `MIDI:ch1:note36`; it triggered nothing.
+    MidiLearned(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAction {
+    Press,
+    Release,
+}
+
+/// One key going down or up, with the key already mapped to its display name (`Ctrl`, `1`, `Numpad1`...).
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyEvent {
+    pub key: String,
+    pub action: KeyAction,
+    pub time: SystemTime,
+}
+
+/// Somewhere key events come from.
+pub trait InputSource: Send + 'static {
+    fn name(&self) -> &'static str;
+
+    /// Blocks, feeding events into `sink` until the source runs dry or nobody listens anymore.
+    fn run(self: Box<Self>, sink: EventSink);
+}
+
+/// Where an [`InputSource`] sends its events.
+pub struct EventSink {
+    events: mpsc::Sender<KeyEvent>,
+    combos: broadcast::Sender<InputEvent>,
+    /// Until when the next MIDI control pressed is learned instead of triggering.
+    midi_learn: Arc<Mutex<Option<Instant>>>,
+}
+
+impl EventSink {
+    /// Returns `false` once the hub has stopped listening, so the source can stop too.
+    pub fn send(&self, event: KeyEvent) -> bool {
+        if event.action == KeyAction::Press && event.key.starts_with(MIDI_PREFIX) && self.learn(&event.key) {
+            return true;
+        }
+        self.events.send(event).is_ok()
+    }
+
+    /// Whether any panel is subscribed. Sources that cost something to run can idle until one is.
+    pub fn has_listeners(&self) -> bool {
+        self.combos.receiver_count() > 0
+    }
+
+    /// Hands `control` to the panels instead of the combo detector if MIDI learn is on, which ends it.
+    fn learn(&self, control: &str) -> bool {
+        let learning = self.midi_learn.lock().unwrap().take();
+        if learning.is_none_or(|until| Instant::now() >= until) {
+            return false;
+        }
+        println!("Learned MIDI control: {}", control);
+        let _ = self.combos.send(InputEvent::MidiLearned(control.to_string()));
+        true
+    }
+}
+
+/// Picks the source for this run: a script given on the command line wins over the configured backend.
+pub fn source_for(settings: &InputSettings, script: Option<&Path>) -> Result<Box<dyn InputSource>, String> {
+    if let Some(path) = script {
+        let source = ScriptedSource::from_file(path)
+            .map_err(|e| format!("Failed to load input script {}: {}", path.display(), e))?;
+        return Ok(Box::new(source));
+    }
+    Ok(match settings.backend {
+        InputBackend::Rdev => Box::new(RdevSource),
+        InputBackend::DeviceQuery => Box::new(DeviceStateSource),
+    })
+}
+
+/// Picks the MIDI source for this run, if any: a file given on the command line wins over the
+/// controllers enabled in the config.
+pub fn midi_source_for(settings: &MidiSettings, file: Option<&Path>) -> Result<Option<Box<dyn InputSource>>, String> {
+    if let Some(path) = file {
+        let source = MidiFileSource::from_file(path)
+            .map_err(|e| format!("Failed to load MIDI file {}: {}", path.display(), e))?;
+        return Ok(Some(Box::new(source)));
+    }
+    Ok(settings
+        .enabled
+        .then(|| Box::new(MidiSource::new(settings.port.clone(), settings.virtual_port)) as Box<dyn InputSource>))
+}
+
+pub struct InputHub {
+    combos: broadcast::Sender<InputEvent>,
+    running_tasks: AtomicUsize,
+    midi_learn: Arc<Mutex<Option<Instant>>>,
+    resolver: Mutex<ComboResolver>,
+    gate: Arc<TriggerGate>,
+}
+
+impl InputHub {
+    /// Runs each source on its own thread (`DeviceState` is not `Send` on X11, and `rdev::listen`
+    /// blocks forever) and turns their events into combos on another, so a MIDI pad and a key
+    /// held together make one combo.
+    pub fn start(
+        sources: Vec<Box<dyn InputSource>>,
+        settings: &InputSettings,
+        foreground: Box<dyn ForegroundProvider>,
+    ) -> Arc<Self> {
+        let (combos, _) = broadcast::channel(100);
+        let gate = Arc::new(TriggerGate::new(foreground));
+        let hub = Arc::new(InputHub {
+            combos,
+            running_tasks: AtomicUsize::new(0),
+            midi_learn: Arc::new(Mutex::new(None)),
+            resolver: Mutex::new(ComboResolver {
+                policy: NormalizationPolicy::default(),
+                layout: settings.keyboard_layout,
+                profile: None,
+                bindings: HashMap::new(),
+                sequences: SequenceMatcher::new(Duration::from_millis(settings.sequence_timeout_ms)),
+                gestures: GestureClassifier::new(),
+                gate: Arc::clone(&gate),
+                arm_combos: Vec::new(),
+            }),
+            gate,
+        });
+        let (events, rx) = mpsc::channel();
+        for source in sources {
+            let sink = EventSink {
+                events: events.clone(),
+                combos: hub.combos.clone(),
+                midi_learn: Arc::clone(&hub.midi_learn),
+            };
+            println!("Listening for keys with the {} input source", source.name());
+            let listener = Arc::clone(&hub);
+            thread::Builder::new()
+                .name(format!("{}-listener", source.name()))
+                .spawn(move || {
+                    let _running = RunningTask::new(&listener.running_tasks);
+                    source.run(sink);
+                })
+                .expect("Failed to start input listener thread");
+        }
+        // The detector stops once every source has
+        drop(events);
+
+        let detector = Arc::clone(&hub);
+        let mut tracker = ComboTracker::new(settings.fire_on_press);
+        thread::Builder::new()
+            .name("combo-detector".to_string())
+            .spawn(move || loop {
+                let deadline = detector.resolver.lock().unwrap().deadline();
+                let event = match deadline {
+                    Some(deadline) => match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                        Ok(event) => Some(event),
+                        Err(mpsc::RecvTimeoutError::Timeout) => None,
+                        Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                    },
+                    None => match rx.recv() {
+                        Ok(event) => Some(event),
+                        Err(_) => break,
+                    },
+                };
+
+                let outputs = {
+                    let mut resolver = detector.resolver.lock().unwrap();
+                    match event {
+                        Some(event) => tracker.handle(event).map(|combo| resolver.push(combo)).unwrap_or_default(),
+                        None => resolver.expire(),
+                    }
+                };
+                for output in outputs {
+                    detector.publish(output);
+                }
+            })
+            .expect("Failed to start combo detector thread");
+
+        hub
+    }
+
+    /// Receives every combo detected from now on. Dropping the receiver unsubscribes.
+    pub fn subscribe(&self) -> broadcast::Receiver<InputEvent> {
+        self.combos.subscribe()
+    }
+
+    pub fn subscribers(&self) -> usize {
+        self.combos.receiver_count()
+    }
+
+    /// Input listeners currently running, one per source; fewer means one has stopped.
+    pub fn input_tasks(&self) -> usize {
+        self.running_tasks.load(Ordering::Relaxed)
+    }
+
+    /// Makes sequence and gesture detection follow the current profile's bindings.
+    pub fn set_bindings(&self, config: &Config) {
+        let profile = config.current_profile.as_deref();
+        let bindings = profile.and_then(|profile| config.profiles.get(profile));
+        let settings = profile.map(|profile| config.settings_for(profile)).unwrap_or_default();
+        let timeout = Duration::from_millis(config.input.sequence_timeout_ms);
+
+        let mut resolver = self.resolver.lock().unwrap();
+        resolver.sequences.set_bindings(bindings.into_iter().flat_map(|b| b.keys()), timeout, &settings.normalization);
+        resolver.gestures.set_bindings(bindings, settings.gestures, &settings.normalization);
+        resolver.profile = profile.map(str::to_string);
+        resolver.bindings = bindings
+            .into_iter()
+            .flatten()
+            .map(|(combo, binding)| (settings.normalization.canonical(combo), (combo.clone(), binding.clone())))
+            .collect();
+        resolver.arm_combos = config.input.arm_combo.iter().flat_map(|combo| settings.normalization.renormalize(combo)).collect();
+        resolver.policy = settings.normalization;
+        resolver.layout = config.input.keyboard_layout;
+        self.gate.set_apps(&config.input.trigger_apps);
+    }
+
+    /// Resolves `combo` as if it had just been typed, for remote triggers: the profile's
+    /// normalization, the arm combo and the trigger gate all apply, but sequences and gestures don't.
+    /// Returns the trigger it fired, if any.
+    pub fn inject(&self, combo: &str) -> Option<Trigger> {
+        let now = SystemTime::now();
+        let combo = KeyCombo {
+            combo: combo.to_string(),
+            pressed_at: now,
+            released_at: Some(now),
+            gesture: Gesture::Tap,
+            label: String::new(),
+        };
+        let outputs = self.resolver.lock().unwrap().inject(combo);
+        let trigger = outputs.iter().find_map(|output| match output {
+            InputEvent::Trigger(trigger) => Some(trigger.clone()),
+            _ => None,
+        });
+        for output in outputs {
+            self.publish(output);
+        }
+        trigger
+    }
+
+    /// The key of the current profile's binding called `name`: the key itself, or the file name
+    /// of its sound with or without the extension (`whoosh` for `C:\sfx\whoosh.wav`).
+    pub fn binding_key(&self, name: &str) -> Option<String> {
+        let resolver = self.resolver.lock().unwrap();
+        let matches = |key: &str, binding: &AudioBinding| {
+            let file = Path::new(&binding.path);
+            key == name
+                || [file.file_name(), file.file_stem()]
+                    .into_iter()
+                    .flatten()
+                    .any(|file| file.to_string_lossy().eq_ignore_ascii_case(name))
+        };
+        resolver.bindings.values().find(|(key, binding)| matches(key, binding)).map(|(key, _)| key.clone())
+    }
+
+    /// Makes the next MIDI control pressed within `timeout` come back as `MidiLearned` instead of triggering.
+    pub fn start_midi_learn(&self, timeout: Duration) {
+        *self.midi_learn.lock().unwrap() = Some(Instant::now() + timeout);
+        println!("Learning the next MIDI control");
+    }
+
+    pub fn armed(&self) -> bool {
+        self.gate.armed()
+    }
+
+    /// Arms or disarms triggers from a panel, telling every panel.
+    pub fn set_armed(&self, armed: bool) {
+        self.gate.set_armed(armed);
+        self.publish(InputEvent::Armed(armed));
+    }
+
+    fn publish(&self, event: InputEvent) {
+        match &event {
+            InputEvent::Combo(combo) if combo.gesture != Gesture::Tap => {
+                println!("Detected key combination: {} ({:?})", combo.combo, combo.gesture)
+            }
+            InputEvent::Combo(combo) => println!("Detected key combination: {}", combo.combo),
+            InputEvent::SequencePending(pending) if !pending.strokes.is_empty() => {
+                println!("Waiting for the rest of: {}", pending.strokes.join(", "))
+            }
+            InputEvent::SequencePending(_) => {}
+            InputEvent::Trigger(trigger) => {
+                println!("Triggering {} ({}) for {}", trigger.binding.path, trigger.binding.track, trigger.combo)
+            }
+            InputEvent::Armed(true) => println!("Triggers armed"),
+            InputEvent::Armed(false) => println!("Triggers disarmed"),
+            InputEvent::MidiLearned(_) => {}
+        }
+        // Nobody subscribed just means no panel is connected
+        let _ = self.combos.send(event);
+    }
+}
+
+/// Keeps the hub's sequence bindings in step with the config, whichever connection or edit changed it.
+pub async fn follow_config(
+    hub: Arc<InputHub>,
+    config: Arc<tokio::sync::Mutex<Config>>,
+    mut events: broadcast::Receiver<ServerEvent>,
+) {
+    hub.set_bindings(&*config.lock().await);
+    // A lagged receiver missed some changes, but re-reading the config catches up on all of them
+    while let Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) = events.recv().await {
+        hub.set_bindings(&*config.lock().await);
+    }
+}
+
+/// Everything between a finished chord and what panels receive: normalization with the current
+/// profile's policy, then sequences, then gestures.
+struct ComboResolver {
+    policy: NormalizationPolicy,
+    layout: KeyboardLayout,
+    profile: Option<String>,
+    /// The current profile's bindings by canonical combo, with the key they are stored under.
+    bindings: HashMap<String, (String, AudioBinding)>,
+    gate: Arc<TriggerGate>,
+    /// Every spelling of the arm combo under the policy (`Ctrl` is both `LeftCtrl` and `RightCtrl`
+    /// when sides are kept apart); toggles the gate instead of reaching the panels.
+    arm_combos: Vec<String>,
+    sequences: SequenceMatcher,
+    gestures: GestureClassifier,
+}
+
+impl ComboResolver {
+    fn deadline(&self) -> Option<Instant> {
+        match (self.sequences.deadline(), self.gestures.deadline()) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    fn push(&mut self, mut combo: KeyCombo) -> Vec<InputEvent> {
+        combo.combo = self.policy.normalize(&combo.combo);
+        let events = self.sequences.push(combo);
+        let events = self.classify(events);
+        self.resolve(events)
+    }
+
+    fn inject(&self, mut combo: KeyCombo) -> Vec<InputEvent> {
+        // A remote `Ctrl+1` means whichever side is bound when the profile keeps sides apart
+        let canonical = self.policy.canonical(&combo.combo);
+        combo.combo = self
+            .policy
+            .renormalize(&combo.combo)
+            .into_iter()
+            .find(|spelling| self.bindings.contains_key(spelling) || self.arm_combos.contains(spelling))
+            .unwrap_or(canonical);
+        self.resolve(vec![InputEvent::Combo(combo)])
+    }
+
+    fn expire(&mut self) -> Vec<InputEvent> {
+        let now = Instant::now();
+        let mut events = Vec::new();
+        if self.sequences.deadline().is_some_and(|deadline| deadline <= now) {
+            let expired = self.sequences.expire();
+            events.extend(self.classify(expired));
+        }
+        if self.gestures.deadline().is_some_and(|deadline| deadline <= now) {
+            events.extend(self.gestures.expire().into_iter().map(InputEvent::Combo));
+        }
+        self.resolve(events)
+    }
+
+    fn classify(&mut self, events: Vec<InputEvent>) -> Vec<InputEvent> {
+        let mut classified = Vec::new();
+        for event in events {
+            match event {
+                InputEvent::Combo(combo) => {
+                    classified.extend(self.gestures.push(combo).into_iter().map(InputEvent::Combo))
+                }
+                other => classified.push(other),
+            }
+        }
+        classified
+    }
+
+    /// Labels the events for the keyboard layout and follows each bound combo with its trigger.
+    fn resolve(&self, events: Vec<InputEvent>) -> Vec<InputEvent> {
+        let mut resolved = Vec::new();
+        for event in events {
+            match event {
+                InputEvent::Combo(combo) if self.arm_combos.contains(&self.policy.canonical(&combo.combo)) => {
+                    resolved.push(InputEvent::Armed(self.gate.toggle()));
+                }
+                InputEvent::Combo(mut combo) => {
+                    combo.label = combo_label(&combo.combo, self.layout);
+                    let trigger = self.trigger(&combo);
+                    resolved.push(InputEvent::Combo(combo));
+                    if let Some(trigger) = trigger {
+                        match self.gate.check() {
+                            Ok(()) => resolved.push(InputEvent::Trigger(trigger)),
+                            Err(reason) => println!("Not triggering {}: {}", trigger.combo, reason),
+                        }
+                    }
+                }
+                InputEvent::SequencePending(mut pending) => {
+                    pending.labels = pending.strokes.iter().map(|stroke| combo_label(stroke, self.layout)).collect();
+                    resolved.push(InputEvent::SequencePending(pending));
+                }
+                other => resolved.push(other),
+            }
+        }
+        resolved
+    }
+
+    fn trigger(&self, combo: &KeyCombo) -> Option<Trigger> {
+        let profile = self.profile.clone()?;
+        let (key, binding) = self.bindings.get(&self.policy.canonical(&combo.combo))?;
+        let binding = match combo.gesture {
+            Gesture::Tap => binding,
+            Gesture::Hold => binding.hold.as_deref().unwrap_or(binding),
+            Gesture::DoubleTap => binding.double_tap.as_deref().unwrap_or(binding),
+        };
+        if binding.draft || !binding.has_path() {
+            return None;
+        }
+        Some(Trigger {
+            profile,
+            combo: key.clone(),
+            label: combo.label.clone(),
+            gesture: combo.gesture,
+            binding: AudioBinding { hold: None, double_tap: None, ..binding.clone() },
+        })
+    }
+}
+
+/// Turns key press/release events into combos: every key pressed while at least one is held
+/// belongs to the same combo.
+pub struct ComboTracker {
+    fire_on_press: bool,
+    held: HashSet<String>,
+    chord: Vec<String>,
+    pressed_at: Option<SystemTime>,
+    fired: bool,
+}
+
+impl ComboTracker {
+    pub fn new(fire_on_press: bool) -> Self {
+        ComboTracker { fire_on_press, held: HashSet::new(), chord: Vec::new(), pressed_at: None, fired: false }
+    }
+
+    pub fn handle(&mut self, event: KeyEvent) -> Option<KeyCombo> {
+        match event.action {
+            KeyAction::Press => self.press(event.key, event.time),
+            KeyAction::Release => self.release(&event.key, event.time),
+        }
+    }
+
+    pub fn press(&mut self, key: String, time: SystemTime) -> Option<KeyCombo> {
+        // OS auto-repeat sends more presses for a key that is already down
+        if !self.held.insert(key.clone()) {
+            return None;
+        }
+        if self.pressed_at.is_none() {
+            self.pressed_at = Some(time);
+        }
+        if !self.chord.contains(&key) {
+            self.chord.push(key.clone());
+        }
+
+        if self.fire_on_press && !self.fired && !is_modifier(&key) {
+            self.fired = true;
+            return self.combo(None);
+        }
+        None
+    }
+
+    pub fn release(&mut self, key: &str, time: SystemTime) -> Option<KeyCombo> {
+        // A release we never saw go down (e.g. held before the listener started)
+        if !self.held.remove(key) {
+            return None;
+        }
+        if !self.held.is_empty() {
+            return None;
+        }
+
+        let combo = if self.fired { None } else { self.combo(Some(time)) };
+        self.chord.clear();
+        self.pressed_at = None;
+        self.fired = false;
+        combo
+    }
+
+    fn combo(&self, released_at: Option<SystemTime>) -> Option<KeyCombo> {
+        if self.chord.is_empty() {
+            return None;
+        }
+        Some(KeyCombo {
+            // Normalized later, with the current profile's policy
+            combo: self.chord.join("+"),
+            pressed_at: self.pressed_at?,
+            released_at,
+            gesture: Gesture::Tap,
+            label: String::new(),
+        })
+    }
+}
+
+// Keeps `running_tasks` honest even if the listener thread panics (e.g. no display to open)
+struct RunningTask<'a>(&'a AtomicUsize);
+
+impl<'a> RunningTask<'a> {
+    fn new(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::Relaxed);
+        RunningTask(counter)
+    }
+}
+
+impl Drop for RunningTask<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProfileSettings;
+    use crate::foreground::FixedForeground;
+
+    /// How long the hub has to stay silent after the script ends before a replay counts as done.
+    const QUIET: Duration = Duration::from_millis(200);
+    const GIVE_UP: Duration = Duration::from_secs(5);
+
+    fn config(bindings: Vec<(&str, AudioBinding)>) -> Config {
+        let mut config = Config::default();
+        config.upsert_profile("Default", bindings.into_iter().map(|(combo, b)| (combo.to_string(), b)).collect());
+        config.normalize();
+        config
+    }
+
+    fn distinguishing_sides(mut config: Config) -> Config {
+        let policy = NormalizationPolicy { distinguish_sides: true, ..NormalizationPolicy::default() };
+        config.profile_settings.insert("Default".to_string(), ProfileSettings { normalization: policy, ..Default::default() });
+        config
+    }
+
+    fn start(config: &Config, script: &str) -> Arc<InputHub> {
+        let source = ScriptedSource::parse(script).unwrap();
+        let hub = InputHub::start(vec![Box::new(source)], &config.input, Box::new(FixedForeground(None)));
+        hub.set_bindings(config);
+        hub
+    }
+
+    /// Everything each receiver got, once the script has run out and the detector has gone quiet.
+    fn collect(hub: &InputHub, receivers: &mut [broadcast::Receiver<InputEvent>]) -> Vec<Vec<InputEvent>> {
+        let started = Instant::now();
+        let mut last_event = Instant::now();
+        let mut finished: Option<Instant> = None;
+        let mut events = vec![Vec::new(); receivers.len()];
+        while started.elapsed() < GIVE_UP {
+            // The detector may still be working on the last events when the source stops
+            match (hub.input_tasks(), finished) {
+                (0, None) => finished = Some(Instant::now()),
+                (0, Some(at)) if at.elapsed() >= QUIET && last_event.elapsed() >= QUIET => break,
+                (0, Some(_)) => {}
+                _ => finished = None,
+            }
+            let mut received = false;
+            for (receiver, events) in receivers.iter_mut().zip(&mut events) {
+                while let Ok(event) = receiver.try_recv() {
+                    events.push(event);
+                    received = true;
+                }
+            }
+            if received {
+                last_event = Instant::now();
+            } else {
+                thread::sleep(Duration::from_millis(5));
+            }
+        }
+        events
+    }
+
+    /// Replays `script` through a hub following `config` and returns everything it broadcast.
+    fn replay(config: &Config, script: &str) -> Vec<InputEvent> {
+        let hub = start(config, script);
+        let mut receivers = [hub.subscribe()];
+        collect(&hub, &mut receivers).remove(0)
+    }
+
+    fn combos(events: &[InputEvent]) -> Vec<String> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                InputEvent::Combo(combo) => Some(combo.combo.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn triggers(events: &[InputEvent]) -> Vec<&Trigger> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                InputEvent::Trigger(trigger) => Some(trigger),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chord_is_detected_normalized_and_triggered() {
+        let config = config(vec![("Ctrl+1", AudioBinding::for_file("/sfx/whoosh.wav"))]);
+        // The second press of Numpad1 is OS auto-repeat
+        let events = replay(&config, "0 press LControl\n10 press Numpad1\n40 press Numpad1\n60 release Numpad1\n70 release LControl");
+
+        assert_eq!(combos(&events), ["Ctrl+1"]);
+        let triggers = triggers(&events);
+        assert_eq!(triggers.len(), 1);
+        assert_eq!(triggers[0].profile, "Default");
+        assert_eq!(triggers[0].combo, "Ctrl+1");
+        assert_eq!(triggers[0].gesture, Gesture::Tap);
+        assert_eq!(triggers[0].binding.path, "/sfx/whoosh.wav");
+    }
+
+    #[test]
+    fn binding_stored_in_another_spelling_triggers_under_its_own_key() {
+        let config = config(vec![("1+Ctrl", AudioBinding::for_file("/sfx/whoosh.wav"))]);
+        let events = replay(&config, "0 press 1\n10 press Ctrl\n20 release 1\n30 release Ctrl");
+
+        // Panels look bindings up by the key they stored, so a bound combo is sent under that key
+        assert_eq!(combos(&events), ["1+Ctrl"]);
+        assert_eq!(triggers(&events)[0].combo, "1+Ctrl");
+    }
+
+    #[test]
+    fn unbound_combo_reaches_panels_without_a_trigger() {
+        let config = config(vec![("Ctrl+1", AudioBinding::for_file("/sfx/whoosh.wav"))]);
+        let events = replay(&config, "0 press Alt\n10 press 2\n20 release 2\n30 release Alt");
+
+        assert_eq!(combos(&events), ["Alt+2"]);
+        assert!(triggers(&events).is_empty());
+    }
+
+    #[test]
+    fn sides_stay_apart_when_the_profile_keeps_them_apart() {
+        let config = distinguishing_sides(config(vec![("LeftCtrl+1", AudioBinding::for_file("/sfx/left.wav"))]));
+        let events = replay(
+            &config,
+            "0 press LControl\n10 press 1\n20 release 1\n30 release LControl\n\
+             100 press RControl\n110 press 1\n120 release 1\n130 release RControl",
+        );
+
+        assert_eq!(combos(&events), ["LeftCtrl+1", "RightCtrl+1"]);
+        let triggers = triggers(&events);
+        assert_eq!(triggers.len(), 1);
+        assert_eq!(triggers[0].combo, "LeftCtrl+1");
+    }
+
+    #[test]
+    fn every_subscriber_gets_every_event() {
+        let config = config(vec![("Ctrl+1", AudioBinding::for_file("/sfx/whoosh.wav"))]);
+        // Starts late so both receivers are subscribed before the first event
+        let hub = start(&config, "100 press Ctrl\n110 press 1\n120 release 1\n130 release Ctrl");
+        let mut receivers = [hub.subscribe(), hub.subscribe()];
+        let [first, second]: [Vec<InputEvent>; 2] = collect(&hub, &mut receivers).try_into().unwrap();
+
+        assert_eq!(hub.subscribers(), 2);
+        assert_eq!(combos(&first), ["Ctrl+1"]);
+        assert_eq!(triggers(&first).len(), 1);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn fire_on_press_sends_the_combo_before_any_key_is_released() {
+        let mut config = config(vec![("Ctrl+1", AudioBinding::for_file("/sfx/whoosh.wav"))]);
+        config.input.fire_on_press = true;
+        let events = replay(&config, "0 press Ctrl\n10 press 1\n200 release 1\n210 release Ctrl");
+
+        let fired: Vec<&KeyCombo> = events
+            .iter()
+            .filter_map(|event| match event {
+                InputEvent::Combo(combo) => Some(combo),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].combo, "Ctrl+1");
+        assert_eq!(fired[0].released_at, None);
+        assert_eq!(triggers(&events).len(), 1);
+    }
+
+    #[test]
+    fn multi_stroke_binding_fires_once_complete() {
+        let config = config(vec![("Ctrl+K, 3", AudioBinding::for_file("/sfx/whoosh.wav"))]);
+        let events = replay(&config, "0 press Ctrl\n10 press K\n20 release K\n30 release Ctrl\n100 press 3\n110 release 3");
+
+        let pending: Vec<&PendingSequence> = events
+            .iter()
+            .filter_map(|event| match event {
+                InputEvent::SequencePending(pending) => Some(pending),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[0].strokes, ["Ctrl+K"]);
+        assert_eq!(pending[0].candidates, ["Ctrl+K, 3"]);
+        assert!(pending[1].strokes.is_empty());
+        assert_eq!(combos(&events), ["Ctrl+K, 3"]);
+        assert_eq!(triggers(&events)[0].combo, "Ctrl+K, 3");
+    }
+
+    #[test]
+    fn holding_a_combo_plays_its_hold_sound() {
+        let mut binding = AudioBinding::for_file("/sfx/short.wav");
+        binding.hold = Some(Box::new(AudioBinding::for_file("/sfx/long.wav")));
+        let config = config(vec![("Ctrl+1", binding)]);
+        let events = replay(&config, "0 press Ctrl\n10 press 1\n600 release 1\n610 release Ctrl");
+
+        let triggers = triggers(&events);
+        assert_eq!(triggers.len(), 1);
+        assert_eq!(triggers[0].gesture, Gesture::Hold);
+        assert_eq!(triggers[0].binding.path, "/sfx/long.wav");
+        assert!(triggers[0].binding.hold.is_none());
+    }
+
+    #[test]
+    fn arm_combo_toggles_triggers_without_reaching_panels() {
+        let mut config = config(vec![("Ctrl+1", AudioBinding::for_file("/sfx/whoosh.wav"))]);
+        config.input.arm_combo = Some("Ctrl+F12".to_string());
+        let events = replay(
+            &config,
+            "0 press Ctrl\n10 press F12\n20 release F12\n30 release Ctrl\n\
+             100 press Ctrl\n110 press 1\n120 release 1\n130 release Ctrl",
+        );
+
+        assert_eq!(events.first(), Some(&InputEvent::Armed(false)));
+        assert_eq!(combos(&events), ["Ctrl+1"]);
+        assert!(triggers(&events).is_empty());
+    }
+
+    #[test]
+    fn injected_combo_finds_the_side_the_profile_bound() {
+        let config = distinguishing_sides(config(vec![("RightCtrl+1", AudioBinding::for_file("/sfx/right.wav"))]));
+        let hub = InputHub::start(Vec::new(), &config.input, Box::new(FixedForeground(None)));
+        hub.set_bindings(&config);
+
+        let trigger = hub.inject("Ctrl+1").expect("a remote Ctrl+1 fires the RightCtrl binding");
+        assert_eq!(trigger.combo, "RightCtrl+1");
+        assert!(hub.inject("Ctrl+2").is_none());
+    }
+
+    #[test]
+    fn draft_binding_is_detected_but_never_triggers() {
+        let config = config(vec![
+            ("Ctrl+1", AudioBinding { draft: true, ..AudioBinding::for_file("") }),
+            ("Ctrl+2", AudioBinding::for_file("/sfx/whoosh.wav")),
+        ]);
+        let events = replay(
+            &config,
+            "0 press Ctrl\n10 press 1\n20 release 1\n30 release Ctrl\n\
+             100 press Ctrl\n110 press 2\n120 release 2\n130 release Ctrl",
+        );
+
+        assert_eq!(combos(&events), ["Ctrl+1", "Ctrl+2"]);
+        let triggers = triggers(&events);
+        assert_eq!(triggers.len(), 1);
+        assert_eq!(triggers[0].combo, "Ctrl+2");
+    }
+
+    #[test]
+    fn tracker_ignores_releases_it_never_saw_pressed() {
+        let mut tracker = ComboTracker::new(false);
+        let now = SystemTime::now();
+        assert_eq!(tracker.release("Ctrl", now), None);
+        assert_eq!(tracker.press("Shift".to_string(), now), None);
+        assert_eq!(tracker.press("A".to_string(), now), None);
+        assert_eq!(tracker.release("A", now), None);
+        let combo = tracker.release("Shift", now).expect("the chord ends when the last key goes up");
+        assert_eq!(combo.combo, "Shift+A");
+        assert_eq!(combo.released_at, Some(now));
+    }
+}
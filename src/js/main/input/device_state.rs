@@ -0,0 +1,45 @@
+use super::{EventSink, InputSource, KeyAction, KeyEvent};
+use crate::keys::map_keycode;
+use device_query::{DeviceQuery, DeviceState, Keycode};
+use std::collections::HashSet;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+// With no panel connected there is nobody to deliver to, so check back rarely
+const IDLE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Polls `device_query` and reports the keys that went down or up since the last poll.
+/// Timestamps are only as precise as the poll interval.
+pub struct DeviceStateSource;
+
+impl InputSource for DeviceStateSource {
+    fn name(&self) -> &'static str {
+        "device_query"
+    }
+
+    fn run(self: Box<Self>, sink: EventSink) {
+        let device_state = DeviceState::new();
+        let mut last_keys: HashSet<Keycode> = HashSet::new();
+
+        loop {
+            let keys: HashSet<Keycode> = if sink.has_listeners() {
+                device_state.get_keys().into_iter().collect()
+            } else {
+                HashSet::new()
+            };
+
+            let time = SystemTime::now();
+            let released = last_keys.difference(&keys).map(|key| (key, KeyAction::Release));
+            let pressed = keys.difference(&last_keys).map(|key| (key, KeyAction::Press));
+            for (key, action) in released.chain(pressed) {
+                if !sink.send(KeyEvent { key: map_keycode(key), action, time }) {
+                    return;
+                }
+            }
+            last_keys = keys;
+
+            thread::sleep(if sink.has_listeners() { POLL_INTERVAL } else { IDLE_INTERVAL });
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use crate::foreground::ForegroundProvider;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// Decides whether a resolved binding may fire right now: only while armed, and only while one
+/// of the configured applications has the focus.
+pub struct TriggerGate {
+    armed: AtomicBool,
+    foreground: Box<dyn ForegroundProvider>,
+    /// Lowercased; any application will do when empty.
+    apps: Mutex<Vec<String>>,
+}
+
+impl TriggerGate {
+    pub fn new(foreground: Box<dyn ForegroundProvider>) -> Self {
+        println!("Checking the foreground application with the {} provider", foreground.name());
+        TriggerGate { armed: AtomicBool::new(true), foreground, apps: Mutex::new(Vec::new()) }
+    }
+
+    pub fn armed(&self) -> bool {
+        self.armed.load(Ordering::Relaxed)
+    }
+
+    pub fn set_armed(&self, armed: bool) {
+        self.armed.store(armed, Ordering::Relaxed);
+    }
+
+    /// Flips the armed state and returns the new one.
+    pub fn toggle(&self) -> bool {
+        !self.armed.fetch_xor(true, Ordering::Relaxed)
+    }
+
+    pub fn set_apps(&self, apps: &[String]) {
+        *self.apps.lock().unwrap() = apps.iter().map(|app| app.to_lowercase()).collect();
+    }
+
+    /// `Err` says why triggers are held back.
+    pub fn check(&self) -> Result<(), String> {
+        if !self.armed() {
+            return Err("disarmed".to_string());
+        }
+        let apps = self.apps.lock().unwrap();
+        if apps.is_empty() {
+            return Ok(());
+        }
+        // Without a way to tell, don't get in the way
+        let Some(app) = self.foreground.foreground_app() else {
+            return Ok(());
+        };
+        let focused = app.to_lowercase();
+        if apps.iter().any(|allowed| focused.contains(allowed.as_str())) {
+            Ok(())
+        } else {
+            Err(format!("{} is in the foreground", app))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::foreground::FixedForeground;
+
+    fn gate(foreground: Option<&str>, apps: &[&str]) -> TriggerGate {
+        let gate = TriggerGate::new(Box::new(FixedForeground(foreground.map(str::to_string))));
+        gate.set_apps(&apps.iter().map(|app| app.to_string()).collect::<Vec<_>>());
+        gate
+    }
+
+    const PREMIERE: &[&str] = &["Premiere Pro"];
+
+    #[test]
+    fn listed_app_in_the_foreground_may_trigger() {
+        assert_eq!(gate(Some("Adobe Premiere Pro"), PREMIERE).check(), Ok(()));
+        // Names are matched ignoring case and anywhere in the focused app's name
+        assert_eq!(gate(Some("adobe premiere pro 2025"), &["PREMIERE"]).check(), Ok(()));
+    }
+
+    #[test]
+    fn other_app_in_the_foreground_holds_triggers_back() {
+        assert_eq!(gate(Some("Slack"), PREMIERE).check(), Err("Slack is in the foreground".to_string()));
+    }
+
+    #[test]
+    fn any_app_may_trigger_without_a_list() {
+        assert_eq!(gate(Some("Slack"), &[]).check(), Ok(()));
+    }
+
+    #[test]
+    fn unknown_foreground_does_not_get_in_the_way() {
+        assert_eq!(gate(None, PREMIERE).check(), Ok(()));
+    }
+
+    #[test]
+    fn disarmed_gate_holds_everything_back() {
+        for (foreground, apps) in [(Some("Adobe Premiere Pro"), PREMIERE), (Some("Slack"), PREMIERE), (None, &[][..])] {
+            let gate = gate(foreground, apps);
+            gate.set_armed(false);
+            assert_eq!(gate.check(), Err("disarmed".to_string()));
+        }
+    }
+
+    #[test]
+    fn toggling_rearms_for_the_listed_apps_only() {
+        let premiere = gate(Some("Adobe Premiere Pro"), PREMIERE);
+        let slack = gate(Some("Slack"), PREMIERE);
+        for gate in [&premiere, &slack] {
+            assert!(!gate.toggle());
+            assert!(gate.toggle());
+        }
+        assert_eq!(premiere.check(), Ok(()));
+        assert!(slack.check().is_err());
+    }
+}
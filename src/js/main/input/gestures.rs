@@ -0,0 +1,190 @@
+use super::{Gesture, KeyCombo};
+use crate::config::{GestureThresholds, NormalizationPolicy, ProfileConfig};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, Default)]
+struct GestureSet {
+    hold: bool,
+    double_tap: bool,
+}
+
+/// Tells taps, holds and double taps apart using the press/release times of each combo.
+///
+/// Only single-chord combos whose binding has a `hold` or `doubleTap` sound are classified, so
+/// every other combo still fires right away as a tap. A tap on a combo with a `doubleTap` sound is
+/// held back until the double-tap window closes or the second tap arrives.
+pub struct GestureClassifier {
+    /// Keyed by the normalized combo, so a binding stored as `1+Ctrl` still matches `Ctrl+1`.
+    bindings: HashMap<String, GestureSet>,
+    thresholds: GestureThresholds,
+    policy: NormalizationPolicy,
+    waiting: Option<(KeyCombo, Instant)>,
+}
+
+impl GestureClassifier {
+    pub fn new() -> Self {
+        GestureClassifier {
+            bindings: HashMap::new(),
+            thresholds: GestureThresholds::default(),
+            policy: NormalizationPolicy::default(),
+            waiting: None,
+        }
+    }
+
+    pub fn set_bindings(
+        &mut self,
+        bindings: Option<&ProfileConfig>,
+        thresholds: GestureThresholds,
+        policy: &NormalizationPolicy,
+    ) {
+        self.policy = policy.clone();
+        self.bindings = bindings
+            .into_iter()
+            .flatten()
+            // A sequence's press-to-release time spans all its strokes, which says nothing about holding
+            .filter(|(combo, _)| !combo.contains(','))
+            .filter(|(_, binding)| binding.hold.is_some() || binding.double_tap.is_some())
+            .map(|(combo, binding)| {
+                let gestures = GestureSet { hold: binding.hold.is_some(), double_tap: binding.double_tap.is_some() };
+                (policy.canonical(combo), gestures)
+            })
+            .collect();
+        self.thresholds = thresholds;
+    }
+
+    /// When a held-back tap stops waiting for its second tap, if there is one.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.waiting.as_ref().map(|(_, deadline)| *deadline)
+    }
+
+    pub fn push(&mut self, mut combo: KeyCombo) -> Vec<KeyCombo> {
+        let mut combos = Vec::new();
+        if let Some((first, _)) = self.waiting.take() {
+            if first.combo == combo.combo {
+                combo.pressed_at = first.pressed_at;
+                combo.gesture = Gesture::DoubleTap;
+                return vec![combo];
+            }
+            combos.push(first);
+        }
+
+        let gestures = self.bindings.get(&self.policy.canonical(&combo.combo)).copied().unwrap_or_default();
+        let held_for = combo
+            .released_at
+            .and_then(|released_at| released_at.duration_since(combo.pressed_at).ok());
+        if gestures.hold && held_for.is_some_and(|held| held >= Duration::from_millis(self.thresholds.hold_ms)) {
+            combo.gesture = Gesture::Hold;
+            combos.push(combo);
+        } else if gestures.double_tap {
+            let deadline = Instant::now() + Duration::from_millis(self.thresholds.double_tap_ms);
+            self.waiting = Some((combo, deadline));
+        } else {
+            combos.push(combo);
+        }
+        combos
+    }
+
+    /// The double-tap window closed: the held-back combo was a single tap after all.
+    pub fn expire(&mut self) -> Vec<KeyCombo> {
+        self.waiting.take().map(|(combo, _)| combo).into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AudioBinding;
+    use std::time::SystemTime;
+
+    fn classifier(combo: &str, hold: bool, double_tap: bool) -> GestureClassifier {
+        let mut binding = AudioBinding::for_file("/sfx/tap.wav");
+        binding.hold = hold.then(|| Box::new(AudioBinding::for_file("/sfx/hold.wav")));
+        binding.double_tap = double_tap.then(|| Box::new(AudioBinding::for_file("/sfx/double.wav")));
+        let bindings = ProfileConfig::from([(combo.to_string(), binding)]);
+
+        let mut classifier = GestureClassifier::new();
+        classifier.set_bindings(Some(&bindings), GestureThresholds::default(), &NormalizationPolicy::default());
+        classifier
+    }
+
+    fn held_for(combo: &str, millis: u64) -> KeyCombo {
+        let pressed_at = SystemTime::now();
+        KeyCombo {
+            combo: combo.to_string(),
+            pressed_at,
+            released_at: Some(pressed_at + Duration::from_millis(millis)),
+            gesture: Gesture::Tap,
+            label: String::new(),
+        }
+    }
+
+    fn gestures(combos: &[KeyCombo]) -> Vec<(&str, Gesture)> {
+        combos.iter().map(|combo| (combo.combo.as_str(), combo.gesture)).collect()
+    }
+
+    #[test]
+    fn long_press_is_a_hold_and_short_one_a_tap() {
+        let mut classifier = classifier("Ctrl+1", true, false);
+        assert_eq!(gestures(&classifier.push(held_for("Ctrl+1", 600))), [("Ctrl+1", Gesture::Hold)]);
+        assert_eq!(gestures(&classifier.push(held_for("Ctrl+1", 100))), [("Ctrl+1", Gesture::Tap)]);
+        assert_eq!(classifier.deadline(), None);
+    }
+
+    #[test]
+    fn combo_without_a_hold_sound_is_never_a_hold() {
+        let mut classifier = classifier("Ctrl+1", true, false);
+        assert_eq!(gestures(&classifier.push(held_for("Ctrl+2", 900))), [("Ctrl+2", Gesture::Tap)]);
+    }
+
+    #[test]
+    fn combo_fired_on_press_is_never_a_hold() {
+        let mut classifier = classifier("Ctrl+1", true, false);
+        let mut combo = held_for("Ctrl+1", 0);
+        combo.released_at = None;
+        assert_eq!(gestures(&classifier.push(combo)), [("Ctrl+1", Gesture::Tap)]);
+    }
+
+    #[test]
+    fn second_tap_in_time_makes_a_double_tap() {
+        let mut classifier = classifier("Ctrl+1", false, true);
+        let first = held_for("Ctrl+1", 50);
+        assert!(classifier.push(first.clone()).is_empty());
+        assert!(classifier.deadline().is_some());
+
+        let second = classifier.push(held_for("Ctrl+1", 50));
+        assert_eq!(gestures(&second), [("Ctrl+1", Gesture::DoubleTap)]);
+        assert_eq!(second[0].pressed_at, first.pressed_at);
+        assert_eq!(classifier.deadline(), None);
+    }
+
+    #[test]
+    fn held_back_tap_fires_alone_when_the_window_closes() {
+        let mut classifier = classifier("Ctrl+1", false, true);
+        classifier.push(held_for("Ctrl+1", 50));
+        assert_eq!(gestures(&classifier.expire()), [("Ctrl+1", Gesture::Tap)]);
+        assert!(classifier.expire().is_empty());
+    }
+
+    #[test]
+    fn another_combo_releases_the_held_back_tap_first() {
+        let mut classifier = classifier("Ctrl+1", false, true);
+        classifier.push(held_for("Ctrl+1", 50));
+        assert_eq!(
+            gestures(&classifier.push(held_for("Ctrl+2", 50))),
+            [("Ctrl+1", Gesture::Tap), ("Ctrl+2", Gesture::Tap)]
+        );
+    }
+
+    #[test]
+    fn binding_stored_in_another_spelling_is_classified() {
+        let mut classifier = classifier("1+Ctrl", true, false);
+        assert_eq!(gestures(&classifier.push(held_for("Ctrl+1", 600))), [("Ctrl+1", Gesture::Hold)]);
+    }
+
+    #[test]
+    fn sequences_are_never_classified() {
+        let mut classifier = classifier("Ctrl+K, 3", true, true);
+        assert_eq!(gestures(&classifier.push(held_for("Ctrl+K, 3", 900))), [("Ctrl+K, 3", Gesture::Tap)]);
+    }
+}
@@ -0,0 +1,181 @@
+use super::{EventSink, InputSource, KeyAction, KeyEvent};
+use midir::{MidiInput, MidiInputConnection};
+use std::sync::mpsc;
+use std::time::{Duration, SystemTime};
+
+/// Starts every key name a MIDI message stands for.
+pub const MIDI_PREFIX: &str = "MIDI:";
+const CLIENT_NAME: &str = "AudioImporter";
+/// How often to look for controllers plugged in after startup.
+const RESCAN_INTERVAL: Duration = Duration::from_secs(2);
+
+type MidiSender = mpsc::Sender<Vec<u8>>;
+
+/// The key name and action a MIDI message stands for. Notes are named `MIDI:ch1:note36` and
+/// controllers `MIDI:ch1:cc20`; a controller counts as pressed from value 64 up, like a sustain pedal.
+pub fn midi_key(message: &[u8]) -> Option<(String, KeyAction)> {
+    let (&status, data) = message.split_first()?;
+    let channel = (status & 0x0F) + 1;
+    let (kind, number, action) = match (status & 0xF0, data) {
+        (0x90, [note, velocity, ..]) if *velocity > 0 => ("note", note, KeyAction::Press),
+        // Note-on with velocity 0 is how most controllers send note-off
+        (0x80 | 0x90, [note, ..]) => ("note", note, KeyAction::Release),
+        (0xB0, [controller, value, ..]) => {
+            ("cc", controller, if *value >= 64 { KeyAction::Press } else { KeyAction::Release })
+        }
+        _ => return None,
+    };
+    Some((format!("{}ch{}:{}{}", MIDI_PREFIX, channel, kind, number), action))
+}
+
+/// Notes and controllers from every MIDI input port (or those whose name contains `port`), plus
+/// optionally a virtual port other programs can send to, e.g. `aplaymidi` on an ALSA system.
+pub struct MidiSource {
+    port: Option<String>,
+    virtual_port: bool,
+}
+
+impl MidiSource {
+    pub fn new(port: Option<String>, virtual_port: bool) -> Self {
+        MidiSource { port, virtual_port }
+    }
+
+    /// Drops the connections of ports that went away and connects to the matching ports not
+    /// connected yet, so a controller unplugged and plugged back in is picked up again.
+    fn connect_new_ports(&self, connections: &mut Vec<(String, MidiInputConnection<MidiSender>)>, tx: &MidiSender) {
+        let names: Vec<String> = match MidiInput::new(CLIENT_NAME) {
+            Ok(input) => input.ports().iter().filter_map(|port| input.port_name(port).ok()).collect(),
+            Err(_) => return,
+        };
+        for name in prune_gone(connections, &names) {
+            println!("MIDI port {} went away", name);
+        }
+        for name in names {
+            let wanted = self.port.as_deref().is_none_or(|filter| name.contains(filter));
+            // Our own virtual port shows up in the list too
+            if !wanted || name.starts_with(CLIENT_NAME) || connections.iter().any(|(connected, _)| *connected == name) {
+                continue;
+            }
+            match connect(&name, tx.clone()) {
+                Ok(connection) => {
+                    println!("Listening for MIDI on {}", name);
+                    connections.push((name, connection));
+                }
+                Err(e) => eprintln!("Failed to open MIDI port {}: {}", name, e),
+            }
+        }
+    }
+}
+
+/// Removes the entries whose port is no longer in `names` and returns their names.
+fn prune_gone<T>(connections: &mut Vec<(String, T)>, names: &[String]) -> Vec<String> {
+    let (kept, gone) = connections.drain(..).partition(|(name, _)| names.contains(name));
+    *connections = kept;
+    gone.into_iter().map(|(name, _)| name).collect()
+}
+
+impl InputSource for MidiSource {
+    fn name(&self) -> &'static str {
+        "midi"
+    }
+
+    fn run(self: Box<Self>, sink: EventSink) {
+        if let Err(e) = MidiInput::new(CLIENT_NAME) {
+            eprintln!("MIDI input is unavailable: {}", e);
+            return;
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let mut connections = Vec::new();
+        let _virtual_port = match self.virtual_port.then(|| open_virtual(tx.clone())) {
+            Some(Ok(connection)) => {
+                println!("Listening for MIDI on virtual port {}", CLIENT_NAME);
+                Some(connection)
+            }
+            Some(Err(e)) => {
+                eprintln!("Failed to create virtual MIDI port: {}", e);
+                None
+            }
+            None => None,
+        };
+
+        loop {
+            self.connect_new_ports(&mut connections, &tx);
+            let message = match rx.recv_timeout(RESCAN_INTERVAL) {
+                Ok(message) => message,
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => return,
+            };
+            let Some((key, action)) = midi_key(&message) else {
+                continue;
+            };
+            if !sink.send(KeyEvent { key, action, time: SystemTime::now() }) {
+                return;
+            }
+        }
+    }
+}
+
+fn forward(_timestamp: u64, message: &[u8], tx: &mut MidiSender) {
+    let _ = tx.send(message.to_vec());
+}
+
+fn connect(name: &str, tx: MidiSender) -> Result<MidiInputConnection<MidiSender>, String> {
+    // A port belongs to the client that listed it
+    let input = MidiInput::new(CLIENT_NAME).map_err(|e| e.to_string())?;
+    let port = input
+        .ports()
+        .into_iter()
+        .find(|port| input.port_name(port).is_ok_and(|port_name| port_name == name))
+        .ok_or("port went away")?;
+    input.connect(&port, CLIENT_NAME, forward, tx).map_err(|e| e.to_string())
+}
+
+#[cfg(unix)]
+fn open_virtual(tx: MidiSender) -> Result<MidiInputConnection<MidiSender>, String> {
+    use midir::os::unix::VirtualInput;
+
+    let input = MidiInput::new(CLIENT_NAME).map_err(|e| e.to_string())?;
+    input.create_virtual(CLIENT_NAME, forward, tx).map_err(|e| e.to_string())
+}
+
+#[cfg(not(unix))]
+fn open_virtual(_tx: MidiSender) -> Result<MidiInputConnection<MidiSender>, String> {
+    Err("virtual MIDI ports need ALSA or CoreMIDI".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notes_press_and_release() {
+        assert_eq!(midi_key(&[0x90, 36, 100]), Some(("MIDI:ch1:note36".to_string(), KeyAction::Press)));
+        assert_eq!(midi_key(&[0x80, 36, 64]), Some(("MIDI:ch1:note36".to_string(), KeyAction::Release)));
+        // Note-on at velocity 0 is a release
+        assert_eq!(midi_key(&[0x9F, 36, 0]), Some(("MIDI:ch16:note36".to_string(), KeyAction::Release)));
+    }
+
+    #[test]
+    fn controllers_press_from_half_way_up() {
+        assert_eq!(midi_key(&[0xB2, 64, 64]), Some(("MIDI:ch3:cc64".to_string(), KeyAction::Press)));
+        assert_eq!(midi_key(&[0xB2, 64, 63]), Some(("MIDI:ch3:cc64".to_string(), KeyAction::Release)));
+    }
+
+    #[test]
+    fn other_messages_are_not_keys() {
+        assert_eq!(midi_key(&[0xC0, 5]), None);
+        assert_eq!(midi_key(&[0xE0, 0, 64]), None);
+        assert_eq!(midi_key(&[]), None);
+    }
+
+    #[test]
+    fn connections_to_ports_that_went_away_are_dropped() {
+        let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        let mut connections: Vec<(String, ())> = names(&["Pads", "Keys", "Pedal"]).into_iter().map(|name| (name, ())).collect();
+
+        assert_eq!(prune_gone(&mut connections, &names(&["Keys", "New"])), ["Pads", "Pedal"]);
+        assert_eq!(connections, [("Keys".to_string(), ())]);
+        assert!(prune_gone(&mut connections, &names(&["Keys"])).is_empty());
+    }
+}
@@ -0,0 +1,272 @@
+use super::midi::midi_key;
+use super::scripted::{ScriptedEvent, ScriptedSource};
+use super::{EventSink, InputSource};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Microseconds per quarter note until a file sets its own tempo (120 bpm).
+const DEFAULT_TEMPO: u64 = 500_000;
+
+enum TrackEvent {
+    Tempo(u64),
+    Message(Vec<u8>),
+}
+
+/// Replays the notes and controllers of a Standard MIDI File as key events, at the file's tempo,
+/// so MIDI bindings can be tested without a controller or an ALSA sequencer.
+pub struct MidiFileSource(ScriptedSource);
+
+impl MidiFileSource {
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| e.to_string())?;
+        Ok(MidiFileSource(ScriptedSource::new(parse(&bytes)?)))
+    }
+}
+
+impl InputSource for MidiFileSource {
+    fn name(&self) -> &'static str {
+        "midi-file"
+    }
+
+    fn run(self: Box<Self>, sink: EventSink) {
+        Box::new(self.0).run(sink);
+    }
+}
+
+fn parse(bytes: &[u8]) -> Result<Vec<ScriptedEvent>, String> {
+    let mut reader = Reader { bytes, position: 0 };
+    if reader.take(4)? != b"MThd" {
+        return Err("not a MIDI file".to_string());
+    }
+    let header = reader.sized()?;
+    let [_, _, _, _, high, low, ..] = header[..] else {
+        return Err("truncated header".to_string());
+    };
+    let division = u16::from_be_bytes([high, low]);
+
+    let mut events = Vec::new();
+    while reader.position < bytes.len() {
+        let kind = reader.take(4)?;
+        let chunk = reader.sized()?;
+        if kind == b"MTrk" {
+            events.extend(parse_track(chunk)?);
+        }
+    }
+    // Stable, so events on the same tick keep their order within a track
+    events.sort_by_key(|(tick, _)| *tick);
+
+    // Timed from the last tempo change rather than tick by tick, so rounding never accumulates
+    let mut tempo = DEFAULT_TEMPO;
+    let (mut tempo_tick, mut tempo_micros) = (0, 0);
+    let mut timed = Vec::new();
+    for (tick, event) in events {
+        let micros = tempo_micros + ticks_to_micros(tick - tempo_tick, division, tempo);
+        match event {
+            TrackEvent::Tempo(new_tempo) => {
+                tempo = new_tempo;
+                (tempo_tick, tempo_micros) = (tick, micros);
+            }
+            TrackEvent::Message(message) => {
+                if let Some((key, action)) = midi_key(&message) {
+                    timed.push(ScriptedEvent { at: Duration::from_micros(micros), key, action });
+                }
+            }
+        }
+    }
+    Ok(timed)
+}
+
+fn ticks_to_micros(ticks: u64, division: u16, tempo: u64) -> u64 {
+    if division & 0x8000 == 0 {
+        ticks * tempo / u64::from(division.max(1))
+    } else {
+        // SMPTE: frames per second in the high byte (negated), ticks per frame in the low one
+        let fps = u64::from(((division >> 8) as u8).wrapping_neg());
+        let ticks_per_frame = u64::from(division & 0xFF);
+        ticks * 1_000_000 / (fps * ticks_per_frame).max(1)
+    }
+}
+
+fn parse_track(bytes: &[u8]) -> Result<Vec<(u64, TrackEvent)>, String> {
+    let mut reader = Reader { bytes, position: 0 };
+    let mut events = Vec::new();
+    let (mut tick, mut running_status) = (0, None);
+
+    while reader.position < bytes.len() {
+        tick += reader.var_len()?;
+        let status = match reader.peek()? {
+            status if status & 0x80 != 0 => {
+                reader.position += 1;
+                status
+            }
+            _ => running_status.ok_or("data byte without a status")?,
+        };
+        match status {
+            0xFF => {
+                let kind = reader.take(1)?[0];
+                let data = reader.var_sized()?;
+                match (kind, data) {
+                    (0x2F, _) => break,
+                    (0x51, [a, b, c]) => events.push((tick, TrackEvent::Tempo(u64::from_be_bytes([0, 0, 0, 0, 0, *a, *b, *c])))),
+                    _ => {}
+                }
+            }
+            0xF0 | 0xF7 => {
+                reader.var_sized()?;
+                running_status = None;
+            }
+            _ => {
+                let length = if matches!(status & 0xF0, 0xC0 | 0xD0) { 1 } else { 2 };
+                let data = reader.take(length)?;
+                events.push((tick, TrackEvent::Message([&[status], data].concat())));
+                running_status = Some(status);
+            }
+        }
+    }
+    Ok(events)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let bytes = self.bytes.get(self.position..self.position + length).ok_or("unexpected end of file")?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn peek(&self) -> Result<u8, String> {
+        self.bytes.get(self.position).copied().ok_or_else(|| "unexpected end of file".to_string())
+    }
+
+    /// Bytes preceded by their 32-bit length, like a chunk's.
+    fn sized(&mut self) -> Result<&'a [u8], String> {
+        let length = self.take(4)?;
+        self.take(u32::from_be_bytes([length[0], length[1], length[2], length[3]]) as usize)
+    }
+
+    /// Bytes preceded by their variable-length length, like a meta event's.
+    fn var_sized(&mut self) -> Result<&'a [u8], String> {
+        let length = self.var_len()?;
+        self.take(length as usize)
+    }
+
+    /// A variable-length quantity: 7 bits per byte, high bit set on all but the last.
+    fn var_len(&mut self) -> Result<u64, String> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let byte = self.take(1)?[0];
+            value = (value << 7) | u64::from(byte & 0x7F);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("variable-length number too long".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::KeyAction;
+
+    /// A format-0 file with `division` ticks per quarter note and one track of `events`.
+    fn midi_file(division: u16, events: &[u8]) -> Vec<u8> {
+        let mut bytes = b"MThd".to_vec();
+        bytes.extend(6u32.to_be_bytes());
+        bytes.extend([0, 0, 0, 1]);
+        bytes.extend(division.to_be_bytes());
+        bytes.extend(b"MTrk");
+        bytes.extend((events.len() as u32).to_be_bytes());
+        bytes.extend(events);
+        bytes
+    }
+
+    fn timeline(events: &[ScriptedEvent]) -> Vec<(u128, &str, KeyAction)> {
+        events.iter().map(|event| (event.at.as_millis(), event.key.as_str(), event.action)).collect()
+    }
+
+    #[test]
+    fn notes_and_controllers_become_timed_key_events() {
+        // 500 ticks per quarter note at the default 120 bpm: 1 ms per tick
+        let events = parse(&midi_file(
+            500,
+            &[
+                0x00, 0x99, 36, 100, // note on, channel 10
+                0x83, 0x74, 0x89, 36, 0, // 500 ticks later, note off
+                0x00, 0xB0, 20, 127, // cc20 pressed
+                0x81, 0x7A, 20, 0, // 250 ticks later, running status: cc20 released
+                0x00, 0xFF, 0x2F, 0x00, // end of track
+            ],
+        ))
+        .unwrap();
+
+        assert_eq!(
+            timeline(&events),
+            [
+                (0, "MIDI:ch10:note36", KeyAction::Press),
+                (500, "MIDI:ch10:note36", KeyAction::Release),
+                (500, "MIDI:ch1:cc20", KeyAction::Press),
+                (750, "MIDI:ch1:cc20", KeyAction::Release),
+            ]
+        );
+    }
+
+    #[test]
+    fn tempo_changes_apply_from_where_they_are() {
+        let events = parse(&midi_file(
+            100,
+            &[
+                0x00, 0xFF, 0x51, 0x03, 0x0F, 0x42, 0x40, // 60 bpm
+                0x64, 0x90, 60, 90, // one quarter note later
+                0x00, 0xFF, 0x2F, 0x00,
+            ],
+        ))
+        .unwrap();
+        assert_eq!(timeline(&events), [(1000, "MIDI:ch1:note60", KeyAction::Press)]);
+    }
+
+    #[test]
+    fn timing_does_not_drift_when_a_tick_is_not_a_whole_microsecond() {
+        // 480 ticks per quarter note at 120 bpm: 1041.67 µs per tick
+        let events = parse(&midi_file(
+            480,
+            &[
+                0x83, 0x60, 0x90, 60, 90, // one quarter note in
+                0x8B, 0x20, 0x90, 62, 90, // three more
+                0x00, 0xFF, 0x2F, 0x00,
+            ],
+        ))
+        .unwrap();
+        assert_eq!(
+            events.iter().map(|event| event.at).collect::<Vec<_>>(),
+            [Duration::from_millis(500), Duration::from_millis(2000)]
+        );
+    }
+
+    #[test]
+    fn sysex_and_other_meta_events_are_skipped() {
+        let events = parse(&midi_file(
+            96,
+            &[
+                0x00, 0xF0, 0x03, 0x7E, 0x7F, 0xF7, // sysex
+                0x00, 0xFF, 0x03, 0x04, b'D', b'r', b'u', b'm', // track name
+                0x00, 0xC0, 5, // program change: not a key
+                0x00, 0x90, 40, 64,
+            ],
+        ))
+        .unwrap();
+        assert_eq!(timeline(&events), [(0, "MIDI:ch1:note40", KeyAction::Press)]);
+    }
+
+    #[test]
+    fn broken_files_are_refused() {
+        assert_eq!(parse(b"RIFF").unwrap_err(), "not a MIDI file");
+        assert!(parse(&midi_file(96, &[0x00, 0x90, 40])).is_err());
+        assert_eq!(parse(&midi_file(96, &[0x00, 40, 64])).unwrap_err(), "data byte without a status");
+    }
+}
@@ -0,0 +1,54 @@
+use super::{EventSink, InputSource, KeyAction, KeyEvent};
+use crate::keys::map_rdev_key;
+use std::sync::mpsc;
+use std::sync::OnceLock;
+use std::thread;
+
+/// Press/release events from the OS keyboard hook, timestamped by the OS.
+pub struct RdevSource;
+
+static RDEV_EVENTS: OnceLock<mpsc::Sender<Option<rdev::Event>>> = OnceLock::new();
+
+// `rdev::listen` only takes a plain fn, so events leave the hook through a global channel
+fn forward_rdev_event(event: rdev::Event) {
+    if let Some(tx) = RDEV_EVENTS.get() {
+        let _ = tx.send(Some(event));
+    }
+}
+
+impl InputSource for RdevSource {
+    fn name(&self) -> &'static str {
+        "rdev"
+    }
+
+    fn run(self: Box<Self>, sink: EventSink) {
+        let (tx, rx) = mpsc::channel();
+        if RDEV_EVENTS.set(tx).is_err() {
+            eprintln!("rdev listener is already running");
+            return;
+        }
+        thread::Builder::new()
+            .name("rdev-hook".to_string())
+            .spawn(|| {
+                if let Err(e) = rdev::listen(forward_rdev_event) {
+                    eprintln!("rdev keyboard hook failed: {:?}", e);
+                }
+                // `None` tells the source the hook is gone
+                if let Some(tx) = RDEV_EVENTS.get() {
+                    let _ = tx.send(None);
+                }
+            })
+            .expect("Failed to start rdev hook thread");
+
+        while let Ok(Some(event)) = rx.recv() {
+            let (key, action) = match event.event_type {
+                rdev::EventType::KeyPress(key) => (key, KeyAction::Press),
+                rdev::EventType::KeyRelease(key) => (key, KeyAction::Release),
+                _ => continue,
+            };
+            if !sink.send(KeyEvent { key: map_rdev_key(&key), action, time: event.time }) {
+                return;
+            }
+        }
+    }
+}
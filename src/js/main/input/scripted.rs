@@ -0,0 +1,95 @@
+use super::{EventSink, InputSource, KeyAction, KeyEvent};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+const WAIT_FOR_LISTENER_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A key event at a fixed offset from the start of the script.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptedEvent {
+    pub at: Duration,
+    pub key: String,
+    pub action: KeyAction,
+}
+
+/// Replays timed key events, so combo detection and delivery can run without a keyboard or display.
+///
+/// Scripts have one event per line: milliseconds since the start, `press` or `release`, and the
+/// key name as the panel shows it. Blank lines and lines starting with `#` are ignored:
+///
+/// ```text
+/// # Ctrl+1
+/// 0   press   Ctrl
+/// 40  press   1
+/// 120 release 1
+/// 150 release Ctrl
+/// ```
+///
+/// Replay starts once the first panel subscribes, and event timestamps are exactly the start time
+/// plus each offset.
+pub struct ScriptedSource {
+    events: Vec<ScriptedEvent>,
+}
+
+impl ScriptedSource {
+    pub fn new(mut events: Vec<ScriptedEvent>) -> Self {
+        events.sort_by_key(|event| event.at);
+        ScriptedSource { events }
+    }
+
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let script = fs::read_to_string(path)?;
+        Self::parse(&script).map_err(|message| io::Error::new(io::ErrorKind::InvalidData, message))
+    }
+
+    pub fn parse(script: &str) -> Result<Self, String> {
+        let mut events = Vec::new();
+        for (index, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = |reason: &str| format!("line {}: {} ({})", index + 1, reason, line);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [at, action, key] = fields[..] else {
+                return Err(invalid("expected `<ms> press|release <key>`"));
+            };
+            let at = at.parse().map_err(|_| invalid("invalid offset"))?;
+            let action = match action {
+                "press" | "down" => KeyAction::Press,
+                "release" | "up" => KeyAction::Release,
+                _ => return Err(invalid("expected press or release")),
+            };
+            events.push(ScriptedEvent { at: Duration::from_millis(at), key: key.to_string(), action });
+        }
+        Ok(Self::new(events))
+    }
+}
+
+impl InputSource for ScriptedSource {
+    fn name(&self) -> &'static str {
+        "scripted"
+    }
+
+    fn run(self: Box<Self>, sink: EventSink) {
+        // Events sent before anyone is connected would be lost
+        while !sink.has_listeners() {
+            thread::sleep(WAIT_FOR_LISTENER_INTERVAL);
+        }
+
+        let started = Instant::now();
+        let start_time = SystemTime::now();
+        for event in self.events {
+            thread::sleep((started + event.at).saturating_duration_since(Instant::now()));
+            let event = KeyEvent { key: event.key, action: event.action, time: start_time + event.at };
+            if !sink.send(event) {
+                return;
+            }
+        }
+        println!("Input script finished");
+    }
+}
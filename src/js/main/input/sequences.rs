@@ -0,0 +1,192 @@
+use super::{InputEvent, KeyCombo, PendingSequence};
+use crate::config::NormalizationPolicy;
+use crate::keys::SEQUENCE_SEPARATOR;
+use std::time::{Duration, Instant};
+
+struct SequenceBinding {
+    strokes: Vec<String>,
+    /// The key as stored in the profile, which is what panels look up.
+    key: String,
+}
+
+/// Groups combos into the multi-stroke bindings of the current profile (`Ctrl+K, 3`, `F13, F13`).
+///
+/// While the strokes so far start a longer binding, they are held back and the panels are told a
+/// sequence is pending. A stroke that is both bound on its own and the start of a longer binding
+/// waits for the timeout: the longer binding wins if its next stroke comes in time, otherwise the
+/// single chord fires. Strokes that end up matching nothing are sent as typed.
+pub struct SequenceMatcher {
+    bindings: Vec<SequenceBinding>,
+    timeout: Duration,
+    pending: Vec<KeyCombo>,
+    last_stroke: Option<Instant>,
+}
+
+impl SequenceMatcher {
+    pub fn new(timeout: Duration) -> Self {
+        SequenceMatcher { bindings: Vec::new(), timeout, pending: Vec::new(), last_stroke: None }
+    }
+
+    pub fn set_bindings<'a>(
+        &mut self,
+        keys: impl IntoIterator<Item = &'a String>,
+        timeout: Duration,
+        policy: &NormalizationPolicy,
+    ) {
+        self.bindings = keys
+            .into_iter()
+            .map(|key| SequenceBinding { strokes: policy.split_sequence(key), key: key.clone() })
+            .collect();
+        self.timeout = timeout;
+    }
+
+    /// When the pending sequence gives up waiting, if there is one.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.last_stroke.map(|last| last + self.timeout)
+    }
+
+    pub fn push(&mut self, combo: KeyCombo) -> Vec<InputEvent> {
+        self.pending.push(combo);
+        let strokes: Vec<String> = self.pending.iter().map(|c| c.combo.clone()).collect();
+
+        let candidates: Vec<String> = self
+            .bindings
+            .iter()
+            .filter(|b| b.strokes.len() > strokes.len() && b.strokes.starts_with(&strokes))
+            .map(|b| b.key.clone())
+            .collect();
+        if !candidates.is_empty() {
+            self.last_stroke = Some(Instant::now());
+            return vec![InputEvent::SequencePending(PendingSequence {
+                strokes,
+                labels: Vec::new(),
+                candidates,
+                timeout_ms: self.timeout.as_millis() as u64,
+            })];
+        }
+
+        if self.pending.len() > 1 && self.exact_match().is_none() {
+            // The new stroke broke the sequence: settle what was pending, then start over from it
+            let stroke = self.pending.pop().expect("pending has more than one stroke");
+            let mut events = self.flush();
+            events.extend(self.push(stroke));
+            return events;
+        }
+        self.flush()
+    }
+
+    /// The pending sequence timed out: fire whatever it adds up to.
+    pub fn expire(&mut self) -> Vec<InputEvent> {
+        self.flush()
+    }
+
+    fn exact_match(&self) -> Option<&SequenceBinding> {
+        self.bindings.iter().find(|b| {
+            b.strokes.len() == self.pending.len()
+                && b.strokes.iter().zip(&self.pending).all(|(stroke, combo)| *stroke == combo.combo)
+        })
+    }
+
+    fn flush(&mut self) -> Vec<InputEvent> {
+        let (Some(first), Some(last)) = (self.pending.first(), self.pending.last()) else {
+            return Vec::new();
+        };
+        let combo = match self.exact_match() {
+            Some(binding) => binding.key.clone(),
+            None => self.pending.iter().map(|c| c.combo.as_str()).collect::<Vec<_>>().join(SEQUENCE_SEPARATOR),
+        };
+        let mut events = vec![InputEvent::Combo(KeyCombo {
+            combo,
+            pressed_at: first.pressed_at,
+            released_at: last.released_at,
+            gesture: first.gesture,
+            label: String::new(),
+        })];
+
+        // Only panels that were told about a pending sequence need to hear it is over
+        if self.last_stroke.is_some() {
+            events.push(InputEvent::SequencePending(PendingSequence::default()));
+        }
+        self.pending.clear();
+        self.last_stroke = None;
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::Gesture;
+    use std::time::SystemTime;
+
+    fn matcher(keys: &[&str]) -> SequenceMatcher {
+        let keys: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+        let mut matcher = SequenceMatcher::new(Duration::from_millis(1000));
+        matcher.set_bindings(&keys, Duration::from_millis(1000), &NormalizationPolicy::default());
+        matcher
+    }
+
+    fn stroke(combo: &str) -> KeyCombo {
+        let now = SystemTime::now();
+        KeyCombo { combo: combo.to_string(), pressed_at: now, released_at: Some(now), gesture: Gesture::Tap, label: String::new() }
+    }
+
+    /// `Combo` events by name and `SequencePending` events as their strokes joined with `|`.
+    fn names(events: &[InputEvent]) -> Vec<String> {
+        events
+            .iter()
+            .map(|event| match event {
+                InputEvent::Combo(combo) => combo.combo.clone(),
+                InputEvent::SequencePending(pending) => format!("pending[{}]", pending.strokes.join("|")),
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stroke_outside_any_sequence_passes_straight_through() {
+        let mut matcher = matcher(&["Ctrl+K, 3"]);
+        assert_eq!(names(&matcher.push(stroke("Ctrl+1"))), ["Ctrl+1"]);
+        assert_eq!(matcher.deadline(), None);
+    }
+
+    #[test]
+    fn completed_sequence_fires_under_its_stored_key() {
+        let mut matcher = matcher(&["Ctrl+K,3"]);
+        assert_eq!(names(&matcher.push(stroke("Ctrl+K"))), ["pending[Ctrl+K]"]);
+        assert!(matcher.deadline().is_some());
+        assert_eq!(names(&matcher.push(stroke("3"))), ["Ctrl+K,3", "pending[]"]);
+        assert_eq!(matcher.deadline(), None);
+    }
+
+    #[test]
+    fn stroke_that_breaks_the_sequence_settles_it_and_starts_over() {
+        let mut matcher = matcher(&["Ctrl+K, 3", "Ctrl+K, 4, 5"]);
+        assert_eq!(names(&matcher.push(stroke("Ctrl+K"))), ["pending[Ctrl+K]"]);
+        assert_eq!(names(&matcher.push(stroke("9"))), ["Ctrl+K", "pending[]", "9"]);
+    }
+
+    #[test]
+    fn broken_sequence_can_start_the_next_one() {
+        let mut matcher = matcher(&["Ctrl+K, 3"]);
+        matcher.push(stroke("Ctrl+K"));
+        assert_eq!(names(&matcher.push(stroke("Ctrl+K"))), ["Ctrl+K", "pending[]", "pending[Ctrl+K]"]);
+    }
+
+    #[test]
+    fn chord_bound_on_its_own_fires_when_the_longer_binding_times_out() {
+        let mut matcher = matcher(&["Ctrl+K", "Ctrl+K, 3"]);
+        assert_eq!(names(&matcher.push(stroke("Ctrl+K"))), ["pending[Ctrl+K]"]);
+        assert_eq!(names(&matcher.expire()), ["Ctrl+K", "pending[]"]);
+        assert!(matcher.expire().is_empty());
+    }
+
+    #[test]
+    fn pending_sequence_lists_every_candidate() {
+        let mut matcher = matcher(&["F13, F13", "F13, F14", "F14"]);
+        let events = matcher.push(stroke("F13"));
+        let InputEvent::SequencePending(pending) = &events[0] else { panic!("expected a pending sequence") };
+        assert_eq!(pending.candidates, ["F13, F13", "F13, F14"]);
+        assert_eq!(pending.timeout_ms, 1000);
+    }
+}
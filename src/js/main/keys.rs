@@ -0,0 +1,371 @@
+use crate::config::{ConfigError, NormalizationPolicy};
+use device_query::Keycode;
+use crate::scancodes::physical_key;
+use std::collections::HashSet;
+
+/// The canonical name of a `device_query` key: the US-QWERTY name of the physical key.
+pub fn map_keycode(key: &Keycode) -> String {
+    physical_key(keycode_name(key))
+}
+
+/// The canonical name of an `rdev` key: the US-QWERTY name of the physical key.
+pub fn map_rdev_key(key: &rdev::Key) -> String {
+    physical_key(rdev_key_name(key))
+}
+
+fn keycode_name(key: &Keycode) -> String {
+    match format!("{:?}", key).as_str() {
+        // Number keys
+        "Key1" => "1".to_string(),
+        "Key2" => "2".to_string(),
+        "Key3" => "3".to_string(),
+        "Key4" => "4".to_string(),
+        "Key5" => "5".to_string(),
+        "Key6" => "6".to_string(),
+        "Key7" => "7".to_string(),
+        "Key8" => "8".to_string(),
+        "Key9" => "9".to_string(),
+        "Key0" => "0".to_string(),
+        
+        // Numpad keys
+        "Numpad0" => "Numpad0".to_string(),
+        "Numpad1" => "Numpad1".to_string(),
+        "Numpad2" => "Numpad2".to_string(),
+        "Numpad3" => "Numpad3".to_string(),
+        "Numpad4" => "Numpad4".to_string(),
+        "Numpad5" => "Numpad5".to_string(),
+        "Numpad6" => "Numpad6".to_string(),
+        "Numpad7" => "Numpad7".to_string(),
+        "Numpad8" => "Numpad8".to_string(),
+        "Numpad9" => "Numpad9".to_string(),
+        
+        // Function keys
+        "F1" => "F1".to_string(),
+        "F2" => "F2".to_string(),
+        "F3" => "F3".to_string(),
+        "F4" => "F4".to_string(),
+        "F5" => "F5".to_string(),
+        "F6" => "F6".to_string(),
+        "F7" => "F7".to_string(),
+        "F8" => "F8".to_string(),
+        "F9" => "F9".to_string(),
+        "F10" => "F10".to_string(),
+        "F11" => "F11".to_string(),
+        "F12" => "F12".to_string(),
+        
+        // Numpad special keys
+        "NumpadSlash" => "NumpadDivide".to_string(),
+        "NumpadAsterisk" => "NumpadMultiply".to_string(),
+        "NumpadMinus" => "NumpadSubtract".to_string(),
+        "NumpadPlus" => "NumpadAdd".to_string(),
+        "NumpadEnter" => "NumpadEnter".to_string(),
+        "NumpadDot" => "NumpadDecimal".to_string(),
+        
+        // Modifier keys (Windows and macOS), with their side for the normalization policy to keep or merge
+        "ControlLeft" => "LeftCtrl".to_string(),
+        "ControlRight" => "RightCtrl".to_string(),
+        "AltLeft" => "LeftAlt".to_string(),
+        "AltRight" => "RightAlt".to_string(),
+        "ShiftLeft" => "LeftShift".to_string(),
+        "ShiftRight" => "RightShift".to_string(),
+        "MetaLeft" => format!("Left{}", meta_name()),
+        "MetaRight" => format!("Right{}", meta_name()),
+        
+        // Other common keys
+        "Space" => "Space".to_string(),
+        "Return" => "Enter".to_string(),
+        "Escape" => "Esc".to_string(),
+        "Tab" => "Tab".to_string(),
+        "CapsLock" => "CapsLock".to_string(),
+        
+        // Default case
+        _ => format!("{:?}", key),
+    }
+}
+
+fn rdev_key_name(key: &rdev::Key) -> String {
+    match format!("{:?}", key).as_str() {
+        // Number keys
+        "Num1" => "1".to_string(),
+        "Num2" => "2".to_string(),
+        "Num3" => "3".to_string(),
+        "Num4" => "4".to_string(),
+        "Num5" => "5".to_string(),
+        "Num6" => "6".to_string(),
+        "Num7" => "7".to_string(),
+        "Num8" => "8".to_string(),
+        "Num9" => "9".to_string(),
+        "Num0" => "0".to_string(),
+
+        // Numpad keys
+        "Kp0" => "Numpad0".to_string(),
+        "Kp1" => "Numpad1".to_string(),
+        "Kp2" => "Numpad2".to_string(),
+        "Kp3" => "Numpad3".to_string(),
+        "Kp4" => "Numpad4".to_string(),
+        "Kp5" => "Numpad5".to_string(),
+        "Kp6" => "Numpad6".to_string(),
+        "Kp7" => "Numpad7".to_string(),
+        "Kp8" => "Numpad8".to_string(),
+        "Kp9" => "Numpad9".to_string(),
+
+        // Numpad special keys
+        "KpDivide" => "NumpadDivide".to_string(),
+        "KpMultiply" => "NumpadMultiply".to_string(),
+        "KpMinus" => "NumpadSubtract".to_string(),
+        "KpPlus" => "NumpadAdd".to_string(),
+        "KpReturn" => "NumpadEnter".to_string(),
+        "KpDelete" => "NumpadDecimal".to_string(),
+
+        // Modifier keys, with their side for the normalization policy to keep or merge
+        "ControlLeft" => "LeftCtrl".to_string(),
+        "ControlRight" => "RightCtrl".to_string(),
+        "Alt" => "LeftAlt".to_string(),
+        "AltGr" => "RightAlt".to_string(),
+        "ShiftLeft" => "LeftShift".to_string(),
+        "ShiftRight" => "RightShift".to_string(),
+        "MetaLeft" => format!("Left{}", meta_name()),
+        "MetaRight" => format!("Right{}", meta_name()),
+
+        // Other common keys, named like device_query's so bindings work with either backend
+        "Return" => "Enter".to_string(),
+        "Escape" => "Esc".to_string(),
+        "UpArrow" => "Up".to_string(),
+        "DownArrow" => "Down".to_string(),
+        "LeftArrow" => "Left".to_string(),
+        "RightArrow" => "Right".to_string(),
+        "BackQuote" => "Grave".to_string(),
+        "SemiColon" => "Semicolon".to_string(),
+        "Quote" => "Apostrophe".to_string(),
+
+        // Letters: KeyA -> A
+        name if name.len() == 4 && name.starts_with("Key") => name[3..].to_string(),
+
+        // Default case (F1-F12, Space, Tab, CapsLock, ...)
+        name => name.to_string(),
+    }
+}
+
+/// Modifier names as combos spell them once sides are merged.
+pub const MODIFIERS: &[&str] = &["Ctrl", "Alt", "Shift", "Win", "Cmd"];
+
+/// Separates the strokes of a multi-stroke binding such as `Ctrl+K, 3`.
+pub const SEQUENCE_SEPARATOR: &str = ", ";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Left,
+    Right,
+}
+
+fn meta_name() -> &'static str {
+    if cfg!(target_os = "macos") { "Cmd" } else { "Win" }
+}
+
+/// The modifier a key name stands for and its side, when the name tells. Accepts what the input
+/// sources emit (`LControl`, `LeftCtrl`, `AltGr`...) as well as the merged names.
+fn parse_modifier(key: &str) -> Option<(&'static str, Option<Side>)> {
+    let modifier = match key {
+        "Ctrl" => ("Ctrl", None),
+        "LControl" | "ControlLeft" | "LeftCtrl" => ("Ctrl", Some(Side::Left)),
+        "RControl" | "ControlRight" | "RightCtrl" => ("Ctrl", Some(Side::Right)),
+        "Alt" => ("Alt", None),
+        "LAlt" | "AltLeft" | "LeftAlt" => ("Alt", Some(Side::Left)),
+        "RAlt" | "AltRight" | "AltGr" | "RightAlt" => ("Alt", Some(Side::Right)),
+        "Shift" => ("Shift", None),
+        "LShift" | "ShiftLeft" | "LeftShift" => ("Shift", Some(Side::Left)),
+        "RShift" | "ShiftRight" | "RightShift" => ("Shift", Some(Side::Right)),
+        "Win" => ("Win", None),
+        "Cmd" => ("Cmd", None),
+        "Meta" => (meta_name(), None),
+        "MetaLeft" | "LeftWin" | "LeftCmd" => (meta_name(), Some(Side::Left)),
+        "MetaRight" | "RightWin" | "RightCmd" => (meta_name(), Some(Side::Right)),
+        _ => return None,
+    };
+    Some(modifier)
+}
+
+/// Whether a mapped key name is a modifier, i.e. can't complete a combo on its own.
+pub fn is_modifier(key: &str) -> bool {
+    parse_modifier(key).is_some()
+}
+
+impl NormalizationPolicy {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut seen = HashSet::new();
+        for modifier in &self.modifier_order {
+            if !MODIFIERS.contains(&modifier.as_str()) || !seen.insert(modifier) {
+                return Err(ConfigError::InvalidModifierOrder(modifier.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Spells a single chord the way this policy stores it: modifiers first in the policy's
+    /// order, then the other keys alphabetically, each key once.
+    pub fn normalize(&self, key_combination: &str) -> String {
+        let keys: Vec<String> = key_combination.split('+').map(|key| self.normalize_key(key.trim())).collect();
+        self.sorted(keys)
+    }
+
+    /// The normalized strokes of a binding key; a plain combo is a sequence of one.
+    pub fn split_sequence(&self, combo: &str) -> Vec<String> {
+        combo.split(',').map(|stroke| self.normalize(stroke.trim())).collect()
+    }
+
+    /// The form two spellings of the same binding key share, e.g. `1+Ctrl` and `Ctrl+1`.
+    pub fn canonical(&self, combo: &str) -> String {
+        self.split_sequence(combo).join(SEQUENCE_SEPARATOR)
+    }
+
+    /// Every combo a stored binding key becomes under this policy. A modifier stored without a side
+    /// turns into both sides when sides are distinguished, so the binding keeps firing from either.
+    pub fn renormalize(&self, combo: &str) -> Vec<String> {
+        let mut sequences = vec![Vec::new()];
+        for stroke in combo.split(',') {
+            let mut chords = vec![Vec::new()];
+            for key in stroke.trim().split('+') {
+                let spellings = match parse_modifier(key.trim()) {
+                    Some((modifier, None)) if self.distinguish_sides => {
+                        vec![format!("Left{}", modifier), format!("Right{}", modifier)]
+                    }
+                    _ => vec![self.normalize_key(key.trim())],
+                };
+                chords = chords
+                    .iter()
+                    .flat_map(|chord: &Vec<String>| {
+                        spellings.iter().map(move |key| [chord.clone(), vec![key.clone()]].concat())
+                    })
+                    .collect();
+            }
+            let chords: Vec<String> = chords.into_iter().map(|keys| self.sorted(keys)).collect();
+            sequences = sequences
+                .iter()
+                .flat_map(|strokes: &Vec<String>| {
+                    chords.iter().map(move |chord| [strokes.clone(), vec![chord.clone()]].concat())
+                })
+                .collect();
+        }
+
+        let mut combos: Vec<String> = sequences.into_iter().map(|strokes| strokes.join(SEQUENCE_SEPARATOR)).collect();
+        combos.sort();
+        combos.dedup();
+        combos
+    }
+
+    fn normalize_key(&self, key: &str) -> String {
+        if let Some((modifier, side)) = parse_modifier(key) {
+            return match side {
+                Some(Side::Left) if self.distinguish_sides => format!("Left{}", modifier),
+                Some(Side::Right) if self.distinguish_sides => format!("Right{}", modifier),
+                _ => modifier.to_string(),
+            };
+        }
+        match key.strip_prefix("Numpad") {
+            Some(digit) if !self.separate_numpad && digit.len() == 1 && digit.chars().all(|c| c.is_ascii_digit()) => {
+                digit.to_string()
+            }
+            _ => key.to_string(),
+        }
+    }
+
+    fn sorted(&self, mut keys: Vec<String>) -> String {
+        let default_order: &[&str] =
+            if cfg!(target_os = "macos") { &["Cmd", "Ctrl", "Alt", "Shift"] } else { &["Ctrl", "Alt", "Shift", "Win"] };
+        let rank = |key: &str| match parse_modifier(key) {
+            Some((modifier, _)) if self.modifier_order.is_empty() => {
+                default_order.iter().position(|m| *m == modifier).unwrap_or(default_order.len())
+            }
+            Some((modifier, _)) => {
+                self.modifier_order.iter().position(|m| m == modifier).unwrap_or(self.modifier_order.len())
+            }
+            None => usize::MAX,
+        };
+
+        keys.sort_by(|a, b| rank(a).cmp(&rank(b)).then_with(|| a.cmp(b)));
+        keys.dedup();
+        keys.join("+")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(distinguish_sides: bool, separate_numpad: bool) -> NormalizationPolicy {
+        NormalizationPolicy { distinguish_sides, separate_numpad, modifier_order: Vec::new() }
+    }
+
+    #[test]
+    fn default_policy_merges_sides_and_numpad() {
+        let policy = NormalizationPolicy::default();
+        assert_eq!(policy.normalize("Numpad1+LControl"), "Ctrl+1");
+        assert_eq!(policy.normalize("RShift+ControlLeft+A"), "Ctrl+Shift+A");
+        assert_eq!(policy.normalize("LControl+RControl+1"), "Ctrl+1");
+        assert_eq!(policy.normalize("NumpadAdd"), "NumpadAdd");
+    }
+
+    #[test]
+    fn policy_can_keep_sides_and_numpad_apart() {
+        let policy = policy(true, true);
+        assert_eq!(policy.normalize("Numpad1+LControl"), "LeftCtrl+Numpad1");
+        assert_eq!(policy.normalize("AltGr+E"), "RightAlt+E");
+        assert_eq!(policy.normalize("Ctrl+1"), "Ctrl+1");
+    }
+
+    #[test]
+    fn modifier_order_is_configurable() {
+        let policy = NormalizationPolicy { modifier_order: vec!["Shift".to_string(), "Ctrl".to_string()], ..policy(false, false) };
+        assert_eq!(policy.normalize("Ctrl+Shift+Alt+Z"), "Shift+Ctrl+Alt+Z");
+        assert!(policy.validate().is_ok());
+
+        let invalid = NormalizationPolicy { modifier_order: vec!["Hyper".to_string()], ..policy.clone() };
+        assert!(matches!(invalid.validate(), Err(ConfigError::InvalidModifierOrder(m)) if m == "Hyper"));
+        let repeated = NormalizationPolicy { modifier_order: vec!["Ctrl".to_string(), "Ctrl".to_string()], ..policy };
+        assert!(repeated.validate().is_err());
+    }
+
+    #[test]
+    fn canonical_normalizes_every_stroke_of_a_sequence() {
+        let policy = NormalizationPolicy::default();
+        assert_eq!(policy.canonical("K+Ctrl,Numpad3"), "Ctrl+K, 3");
+        assert_eq!(policy.split_sequence("F13, F13"), ["F13", "F13"]);
+    }
+
+    #[test]
+    fn renormalize_splits_a_sideless_modifier_into_both_sides() {
+        assert_eq!(policy(true, false).renormalize("Ctrl+1"), ["LeftCtrl+1", "RightCtrl+1"]);
+        assert_eq!(
+            policy(true, false).renormalize("Ctrl+Shift+1"),
+            ["LeftCtrl+LeftShift+1", "LeftCtrl+RightShift+1", "RightCtrl+LeftShift+1", "RightCtrl+RightShift+1"]
+        );
+    }
+
+    #[test]
+    fn renormalize_keeps_a_side_that_was_stored() {
+        assert_eq!(policy(true, false).renormalize("LControl+1"), ["LeftCtrl+1"]);
+    }
+
+    #[test]
+    fn renormalize_merges_back_when_sides_are_merged() {
+        assert_eq!(policy(false, false).renormalize("LeftCtrl+Numpad1"), ["Ctrl+1"]);
+        assert_eq!(policy(false, true).renormalize("LeftCtrl+Numpad1"), ["Ctrl+Numpad1"]);
+    }
+
+    #[test]
+    fn renormalize_expands_each_stroke_of_a_sequence() {
+        assert_eq!(
+            policy(true, false).renormalize("Ctrl+K, 3"),
+            ["LeftCtrl+K, 3", "RightCtrl+K, 3"]
+        );
+    }
+
+    #[test]
+    fn modifiers_alone_are_recognized() {
+        assert!(is_modifier("LControl"));
+        assert!(is_modifier("AltGr"));
+        assert!(is_modifier("Meta"));
+        assert!(!is_modifier("1"));
+        assert!(!is_modifier("MIDI:ch1:note36"));
+    }
+}
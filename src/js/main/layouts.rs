@@ -0,0 +1,131 @@
+//! What the keys are called on the user's keyboard. Combos are always stored under the US-QWERTY
+//! name of the physical key (`1`, `Q`, `Semicolon`), so a profile recorded on an AZERTY machine
+//! still matches on a QWERTZ one; these tables only change what the panels show.
+
+use crate::config::KeyboardLayout;
+use crate::keys::SEQUENCE_SEPARATOR;
+
+/// French AZERTY, unshifted: the number row gives `&é"'(-è_çà` unless Shift is held.
+const AZERTY: &[(&str, &str)] = &[
+    ("Grave", "²"),
+    ("1", "&"),
+    ("2", "é"),
+    ("3", "\""),
+    ("4", "'"),
+    ("5", "("),
+    ("6", "-"),
+    ("7", "è"),
+    ("8", "_"),
+    ("9", "ç"),
+    ("0", "à"),
+    ("Minus", ")"),
+    ("Equal", "="),
+    ("Q", "A"),
+    ("W", "Z"),
+    ("LeftBracket", "^"),
+    ("RightBracket", "$"),
+    ("A", "Q"),
+    ("Semicolon", "M"),
+    ("Apostrophe", "ù"),
+    ("BackSlash", "*"),
+    ("IntlBackslash", "<"),
+    ("Z", "W"),
+    ("M", ","),
+    ("Comma", ";"),
+    ("Dot", ":"),
+    ("Slash", "!"),
+    ("RightAlt", "AltGr"),
+];
+
+/// German QWERTZ, unshifted.
+const QWERTZ: &[(&str, &str)] = &[
+    ("Grave", "^"),
+    ("Minus", "ß"),
+    ("Equal", "´"),
+    ("Y", "Z"),
+    ("Z", "Y"),
+    ("LeftBracket", "Ü"),
+    ("RightBracket", "+"),
+    ("Semicolon", "Ö"),
+    ("Apostrophe", "Ä"),
+    ("BackSlash", "#"),
+    ("IntlBackslash", "<"),
+    ("Comma", ","),
+    ("Dot", "."),
+    ("Slash", "-"),
+    ("RightAlt", "AltGr"),
+];
+
+/// US QWERTY names the keys after their own legends.
+const QWERTY: &[(&str, &str)] = &[
+    ("Grave", "`"),
+    ("Minus", "-"),
+    ("Equal", "="),
+    ("LeftBracket", "["),
+    ("RightBracket", "]"),
+    ("Semicolon", ";"),
+    ("Apostrophe", "'"),
+    ("BackSlash", "\\"),
+    ("Comma", ","),
+    ("Dot", "."),
+    ("Slash", "/"),
+];
+
+fn table(layout: KeyboardLayout) -> &'static [(&'static str, &'static str)] {
+    match layout {
+        KeyboardLayout::Qwerty => QWERTY,
+        KeyboardLayout::Azerty => AZERTY,
+        KeyboardLayout::Qwertz => QWERTZ,
+    }
+}
+
+/// The legend of one canonical key; keys the layout doesn't move keep their name.
+pub fn key_label(key: &str, layout: KeyboardLayout) -> String {
+    table(layout)
+        .iter()
+        .find(|(canonical, _)| *canonical == key)
+        .map_or(key, |(_, label)| label)
+        .to_string()
+}
+
+/// A stored combo or sequence as the user would read it off their keyboard, e.g. `Ctrl+1` is
+/// `Ctrl+&` on AZERTY.
+pub fn combo_label(combo: &str, layout: KeyboardLayout) -> String {
+    combo
+        .split(SEQUENCE_SEPARATOR)
+        .map(|stroke| stroke.split('+').map(|key| key_label(key, layout)).collect::<Vec<_>>().join("+"))
+        .collect::<Vec<_>>()
+        .join(SEQUENCE_SEPARATOR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(keys: &[&str], layout: KeyboardLayout) -> Vec<String> {
+        keys.iter().map(|key| key_label(key, layout)).collect()
+    }
+
+    const KEYS: &[&str] = &["1", "Q", "A", "Y", "Z", "M", "Semicolon", "Minus", "F5"];
+
+    #[test]
+    fn qwerty_only_names_punctuation() {
+        assert_eq!(labels(KEYS, KeyboardLayout::Qwerty), ["1", "Q", "A", "Y", "Z", "M", ";", "-", "F5"]);
+    }
+
+    #[test]
+    fn azerty_names_the_physical_keys() {
+        assert_eq!(labels(KEYS, KeyboardLayout::Azerty), ["&", "A", "Q", "Y", "W", ",", "M", ")", "F5"]);
+    }
+
+    #[test]
+    fn qwertz_names_the_physical_keys() {
+        assert_eq!(labels(KEYS, KeyboardLayout::Qwertz), ["1", "Q", "A", "Z", "Y", "M", "Ö", "ß", "F5"]);
+    }
+
+    #[test]
+    fn combo_labels_every_key_of_every_stroke() {
+        let combo = format!("Ctrl+1{}Shift+Minus", SEQUENCE_SEPARATOR);
+        assert_eq!(combo_label(&combo, KeyboardLayout::Azerty), format!("Ctrl+&{}Shift+)", SEQUENCE_SEPARATOR));
+    }
+}
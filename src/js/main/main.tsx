@@ -3,6 +3,8 @@ import { FaVolumeUp, FaWaveSquare } from 'react-icons/fa';
 import { v4 as uuidv4 } from 'uuid';
 import './styles.css';
 import Modal from './Modal'; // Make sure to import the Modal component
+import { Conflict } from './types';
+import { INCOMPATIBLE_CLOSE_CODE, helloMessage, serverUrl } from '../lib/utils/server';
 const path = window.electron ? require('path') : null;
 
 // Define your types
@@ -11,6 +13,7 @@ interface AudioBinding {
   pitch: number;
   track: string;
   volume: number;
+  draft?: boolean;
 }
 
 interface KeyBinding {
@@ -62,6 +65,13 @@ const Main: React.FC = () => {
   // Profile management state variables
   const [profiles, setProfiles] = useState<string[]>([]);
   const [currentProfile, setCurrentProfile] = useState<string | null>(null);
+  const [pendingSequence, setPendingSequence] = useState<string>('');
+  // How each bound combo reads on the keyboard layout set in the config (`Ctrl+1` is `Ctrl+&` on AZERTY)
+  const [keyLabels, setKeyLabels] = useState<Record<string, string>>({});
+  // What each binding collides with (Premiere, the OS or another binding), reported after a save
+  const [conflicts, setConflicts] = useState<Record<string, string[]>>({});
+  // Whether bound combos fire; toggled by the arm combo set in the config or the button below
+  const [armed, setArmed] = useState(true);
 
   // Ref to hold the last selected profile
   const lastProfileRef = useRef<string | null>(null);
@@ -95,6 +105,12 @@ const Main: React.FC = () => {
     appendToDebugLog(`Current profile changed to: ${currentProfile}`);
   }, [currentProfile]);
 
+  useEffect(() => {
+    if (isWebSocketReady && configArray.length > 0) {
+      socketRef.current?.send(`GET_KEY_LABELS:${JSON.stringify(configArray.map((keyBinding) => keyBinding.key))}`);
+    }
+  }, [configArray, isWebSocketReady]);
+
 
   // Function to append messages to the debug log
   const appendToDebugLog = (message: string) => {
@@ -103,51 +119,9 @@ const Main: React.FC = () => {
   };
 
 
-  const normalizeKeyCombination = (keyCombination: string): string => {
-    const keyMap: { [key: string]: string } = {
-        'lalt': 'alt',
-        'ralt': 'alt',
-        'lcontrol': 'ctrl',
-        'rcontrol': 'ctrl',
-        'controlleft': 'ctrl',
-        'controlright': 'ctrl',
-        'shiftleft': 'shift',
-        'shiftright': 'shift',
-        'metaleft': navigator.platform.toUpperCase().indexOf('MAC') >= 0 ? 'cmd' : 'win',
-        'metaright': navigator.platform.toUpperCase().indexOf('MAC') >= 0 ? 'cmd' : 'win',
-        'numpad1': '1',
-        'numpad2': '2',
-        'numpad3': '3',
-        'numpad4': '4',
-        'numpad5': '5',
-        'numpad6': '6',
-        'numpad7': '7',
-        'numpad8': '8',
-        'numpad9': '9',
-        'numpad0': '0',
-    };
-
-    const isMac = navigator.platform.toUpperCase().indexOf('MAC') >= 0;
-    const priority = isMac 
-        ? ['cmd', 'ctrl', 'alt', 'shift']
-        : ['ctrl', 'alt', 'shift', 'win'];
-
-    const mappedKeys = keyCombination.toLowerCase().split('+')
-        .map(key => keyMap[key] || key)
-        .filter((key, index, self) => self.indexOf(key) === index); // Remove duplicates
-
-    const sortedKeys = mappedKeys.sort((a, b) => {
-        const aIndex = priority.indexOf(a);
-        const bIndex = priority.indexOf(b);
-        if (aIndex === -1 && bIndex === -1) return a.localeCompare(b);
-        if (aIndex === -1) return 1;
-        if (bIndex === -1) return -1;
-        return aIndex - bIndex;
-    });
-
-    // Capitalize the first letter of each key for display
-    return sortedKeys.map(key => key.charAt(0).toUpperCase() + key.slice(1)).join('+');
-};
+  // Combos arrive already normalized with the profile's policy (sides, numpad, modifier order),
+  // so re-mapping them here would undo it
+  const normalizeKeyCombination = (keyCombination: string): string => keyCombination.trim();
 
   const loadConfig = useCallback((profileName?: string) => {
     const profile = profileName || currentProfile;
@@ -355,7 +329,7 @@ const Main: React.FC = () => {
         const newKeyBinding: KeyBinding = {
           id: uuidv4(),
           key: normalizedCombination,
-          binding: { volume: 0, pitch: 0, track: 'A1', path: '' },
+          binding: { volume: 0, pitch: 0, track: 'A1', path: '', draft: true },
         };
   
         const newConfig = [...prevConfig, newKeyBinding];
@@ -409,10 +383,11 @@ function startWebSocketConnection() {
   }
 
   appendToDebugLog('Establishing WebSocket connection...');
-  socketRef.current = new WebSocket('ws://localhost:7878');  // Use the correct address
+  socketRef.current = new WebSocket(serverUrl());  // Carries the auth token the server requires
 
   socketRef.current.onopen = () => {
       appendToDebugLog('Connected to server');
+      socketRef.current?.send(helloMessage('MAIN_PANEL'));
       setIsWebSocketReady(true);
       loadProfiles();
       socketRef.current?.send('GET_LAST_SELECTED_PROFILE');
@@ -425,6 +400,10 @@ function startWebSocketConnection() {
   socketRef.current.onclose = (event) => {
     appendToDebugLog(`Disconnected from server: ${event.reason}`);
     setIsWebSocketReady(false);  // WebSocket is no longer ready
+    if (event.code === INCOMPATIBLE_CLOSE_CODE) {
+      appendToDebugLog('This panel and the server are not compatible; update one of them');
+      return;
+    }
 
     // Try reconnecting after 5 seconds
     setTimeout(() => {
@@ -437,6 +416,11 @@ function startWebSocketConnection() {
     const data = event.data;
     appendToDebugLog(`Received message: ${data}`);
 
+    if (data.startsWith('WELCOME:')) {
+      const [serverVersion, protocol] = data.replace('WELCOME:', '').split(':');
+      appendToDebugLog(`Server ${serverVersion} speaks protocol ${protocol}`);
+    }
+
     if (data.startsWith('PROFILE_SWITCHED:')) {
       const profileName = data.replace('PROFILE_SWITCHED:', '');
       setCurrentProfile(profileName);  // Update the state
@@ -507,8 +491,48 @@ function startWebSocketConnection() {
       }
     }
 
+    if (data.startsWith('SEQUENCE_PENDING:')) {
+      // Strokes typed so far towards a multi-stroke binding; empty once it fired or timed out
+      setPendingSequence(data.replace('SEQUENCE_PENDING:', ''));
+    }
+
+    if (data.startsWith('ARMED:')) {
+      setArmed(data.replace('ARMED:', '') === 'true');
+    }
+
+    if (data.startsWith('CONFLICTS:')) {
+      const { payload } = JSON.parse(data.replace('CONFLICTS:', ''));
+      const byCombo: Record<string, string[]> = {};
+      for (const conflict of payload.conflicts as Conflict[]) {
+        (byCombo[conflict.combo] ??= []).push(`${conflict.stroke}: ${conflict.kind} (${conflict.with})`);
+        // A clash between two bindings is reported once; warn on both of them
+        if (conflict.kind === 'binding') {
+          (byCombo[conflict.with] ??= []).push(`${conflict.stroke}: ${conflict.kind} (${conflict.combo})`);
+        }
+      }
+      setConflicts(byCombo);
+    }
+
+    if (data.startsWith('MIDI_LEARNED:')) {
+      // The pad or knob touched after "Learn MIDI"; the server didn't fire it
+      const control = data.replace('MIDI_LEARNED:', '');
+      if (isListeningForKeyRef.current) {
+        if (isEditingRef.current && idBeingEditedRef.current) {
+          editKeyBind(idBeingEditedRef.current, control);
+        } else {
+          addNewKeyBind(control);
+        }
+        stopListening();
+      }
+    }
+
+    if (data.startsWith('KEY_LABELS:')) {
+      setKeyLabels((labels) => ({ ...labels, ...JSON.parse(data.replace('KEY_LABELS:', '')) }));
+    }
+
     if (data.startsWith('COMBO:')) {
-      const combo = data.replace('COMBO:', '');
+      // Drop the `:hold` / `:doubleTap` gesture suffix; bindings are keyed by the combo alone
+      const combo = data.replace('COMBO:', '').replace(/:(hold|doubleTap)$/, '');
       appendToDebugLog(`Processed combo: ${combo}`);
     
       // Ensure correct profile is being used
@@ -619,6 +643,15 @@ function startWebSocketConnection() {
     }
   };
 
+  // Like "Add Binding", but the next MIDI note or controller is captured without playing its sound
+  const learnMidi = () => {
+    if (!isListeningForKey && !isEditing) {
+      setIsAddingBinding(true);
+      socketRef.current?.send('MIDI_LEARN');
+      startKeyListener();
+    }
+  };
+
   const selectAudioFile = (id: string) => {
     appendToDebugLog(`Attempting to select audio file for id: ${id}`);
     
@@ -646,7 +679,7 @@ function startWebSocketConnection() {
           const keyBinding = configArray.find((kb) => kb.id === id);
           if (keyBinding) {
             // Update the key binding with the full file path
-            updateBinding(id, { ...keyBinding.binding, path: filePath });
+            updateBinding(id, { ...keyBinding.binding, path: filePath, draft: false });
             appendToDebugLog(`Selected audio file for id ${id}: ${filePath}`);
           }
         } else {
@@ -936,6 +969,22 @@ function startWebSocketConnection() {
                 >
                   {isListeningForKey ? 'Listening...' : 'Add Binding'}
                 </button>
+                <button
+                  className="button"
+                  onClick={learnMidi}
+                  disabled={isListeningForKey || isEditing}
+                >
+                  Learn MIDI
+                </button>
+                <button
+                  className={`button ${armed ? 'armed' : 'disarmed'}`}
+                  onClick={() => socketRef.current?.send(`SET_ARMED:${!armed}`)}
+                >
+                  {armed ? 'Armed' : 'Disarmed'}
+                </button>
+                {pendingSequence && (
+                  <span className="pending-sequence">{pendingSequence}, …</span>
+                )}
               </div>
 
               <input
@@ -957,9 +1006,13 @@ function startWebSocketConnection() {
                     <input
                       type="text"
                       readOnly
-                      value={keyBinding.key}
+                      value={keyLabels[keyBinding.key] ?? keyBinding.key}
+                      title={keyBinding.key}
                       className="key-binding-input"
                     />
+                    {conflicts[keyBinding.key] && (
+                      <span className="conflict-warning" title={conflicts[keyBinding.key].join('\n')}>⚠</span>
+                    )}
                     <button
                       className="button"
                       onClick={() => editBinding(keyBinding.id, keyBinding.key)}
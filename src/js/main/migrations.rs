@@ -0,0 +1,122 @@
+use serde_json::{json, Value};
+
+/// Schema version written by this build. Bump it together with a new entry in `MIGRATIONS`.
+pub const CURRENT_SCHEMA_VERSION: u64 = 1;
+
+/// `MIGRATIONS[n]` upgrades a config from schema version `n` to `n + 1`.
+const MIGRATIONS: &[fn(&mut Value)] = &[migrate_v0_to_v1];
+
+/// Reads `schemaVersion`; files written before versioning existed count as version 0.
+pub fn schema_version(config: &Value) -> u64 {
+    config["schemaVersion"].as_u64().unwrap_or(0)
+}
+
+/// Runs every migration step between the file's version and `CURRENT_SCHEMA_VERSION`.
+/// Returns the version the file was at, or `Err` with that version if it is newer than this build.
+pub fn migrate(config: &mut Value) -> Result<u64, u64> {
+    let from = schema_version(config);
+    if from > CURRENT_SCHEMA_VERSION {
+        return Err(from);
+    }
+
+    for (version, step) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+        step(config);
+        config["schemaVersion"] = json!(version as u64 + 1);
+        println!("Migrated config from schema v{} to v{}", version, version + 1);
+    }
+
+    Ok(from)
+}
+
+// v0 -> v1: the shape the old `load_config` patched in, plus binding fields added by later panels
+fn migrate_v0_to_v1(config: &mut Value) {
+    if !config.is_object() {
+        *config = json!({});
+    }
+    if !config["profiles"].is_object() {
+        config["profiles"] = json!({});
+    }
+    if !config["currentProfile"].is_string() {
+        config["currentProfile"] = Value::Null;
+    }
+    if !config["lastSelectedProfile"].is_string() {
+        config["lastSelectedProfile"] = config["currentProfile"].clone();
+    }
+
+    let profiles = config["profiles"].as_object_mut().unwrap();
+    for profile in profiles.values_mut() {
+        let Some(bindings) = profile.as_object_mut() else {
+            *profile = json!({});
+            continue;
+        };
+        for binding in bindings.values_mut().filter(|b| b.is_object()) {
+            // Older panels stored the raw text of the volume/pitch inputs
+            for field in ["volume", "pitch"] {
+                let number = match &binding[field] {
+                    Value::Number(n) => n.as_f64().unwrap_or(0.0),
+                    Value::String(s) => s.trim().parse().unwrap_or(0.0),
+                    _ => 0.0,
+                };
+                binding[field] = json!(number);
+            }
+            if !binding["importInMiddle"].is_boolean() {
+                binding["importInMiddle"] = json!(false);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unversioned_file_gets_the_v1_shape() {
+        let mut config = json!({
+            "currentProfile": "Default",
+            "profiles": {
+                "Default": {
+                    "Ctrl+1": {"volume": "-6", "pitch": " 2 ", "track": "A1", "path": "/sfx/a.wav"},
+                    "Ctrl+2": {"volume": "", "pitch": null, "track": "A1", "path": "/sfx/b.wav", "importInMiddle": true}
+                },
+                "Broken": "not an object"
+            }
+        });
+        assert_eq!(migrate(&mut config), Ok(0));
+
+        assert_eq!(config["schemaVersion"], json!(CURRENT_SCHEMA_VERSION));
+        assert_eq!(config["lastSelectedProfile"], json!("Default"));
+        let default = &config["profiles"]["Default"];
+        assert_eq!(default["Ctrl+1"]["volume"], json!(-6.0));
+        assert_eq!(default["Ctrl+1"]["pitch"], json!(2.0));
+        assert_eq!(default["Ctrl+1"]["importInMiddle"], json!(false));
+        assert_eq!(default["Ctrl+2"]["volume"], json!(0.0));
+        assert_eq!(default["Ctrl+2"]["pitch"], json!(0.0));
+        assert_eq!(default["Ctrl+2"]["importInMiddle"], json!(true));
+        assert_eq!(config["profiles"]["Broken"], json!({}));
+    }
+
+    #[test]
+    fn empty_or_garbage_file_becomes_an_empty_config() {
+        for mut config in [json!({}), json!([1, 2]), json!(null)] {
+            assert_eq!(migrate(&mut config), Ok(0));
+            assert_eq!(config["profiles"], json!({}));
+            assert_eq!(config["currentProfile"], Value::Null);
+            assert_eq!(config["lastSelectedProfile"], Value::Null);
+        }
+    }
+
+    #[test]
+    fn current_file_is_left_alone() {
+        let original = json!({"schemaVersion": CURRENT_SCHEMA_VERSION, "profiles": {"Default": {}}, "extra": true});
+        let mut config = original.clone();
+        assert_eq!(migrate(&mut config), Ok(CURRENT_SCHEMA_VERSION));
+        assert_eq!(config, original);
+    }
+
+    #[test]
+    fn newer_file_is_refused() {
+        let mut config = json!({"schemaVersion": CURRENT_SCHEMA_VERSION + 1});
+        assert_eq!(migrate(&mut config), Err(CURRENT_SCHEMA_VERSION + 1));
+    }
+}
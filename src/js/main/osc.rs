@@ -0,0 +1,368 @@
+//! OSC over UDP for control surfaces such as TouchOSC: `/audioimporter/trigger "Ctrl+1"`,
+//! `/audioimporter/binding <name>` and `/audioimporter/profile <name>`.
+//!
+//! The name can also be the last part of the address (`/audioimporter/binding/whoosh`), for
+//! buttons that can only send a number. A number of 0 is a button being let go and is ignored.
+
+use crate::commands::{self, Session};
+use crate::config::OscSettings;
+use crate::protocol::{Command, Reply, TriggerRequest};
+use crate::state::AppState;
+use std::net::SocketAddr;
+use tokio::net::UdpSocket;
+
+const ADDRESS_PREFIX: &str = "/audioimporter/";
+
+#[derive(Debug, Clone, PartialEq)]
+enum OscArg {
+    String(String),
+    Number(f64),
+    /// Blobs, booleans and the other types we have no use for.
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct OscMessage {
+    address: String,
+    args: Vec<OscArg>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum OscCommand {
+    Trigger(String),
+    Binding(String),
+    Profile(String),
+}
+
+/// Runs the OSC listener until the process exits. Can't-bind is logged, not fatal: the panels still work.
+pub async fn listen(settings: OscSettings, state: AppState) {
+    let socket = match UdpSocket::bind(&settings.bind).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            eprintln!("Can't listen for OSC on {}: {}", settings.bind, e);
+            return;
+        }
+    };
+    println!("Listening for OSC on: {}", settings.bind);
+    serve(socket, state).await;
+}
+
+/// Runs every command that arrives on `socket`.
+async fn serve(socket: UdpSocket, state: AppState) {
+    let mut session = Session::new(commands::NO_CONNECTION);
+    let mut buffer = vec![0; 65536];
+    loop {
+        let (length, peer) = match socket.recv_from(&mut buffer).await {
+            Ok(received) => received,
+            Err(e) => {
+                eprintln!("Failed to read OSC packet: {}", e);
+                continue;
+            }
+        };
+        let messages = match decode_packet(&buffer[..length]) {
+            Ok(messages) => messages,
+            Err(e) => {
+                eprintln!("Ignoring malformed OSC packet from {}: {}", peer, e);
+                continue;
+            }
+        };
+        for message in messages {
+            match parse_command(&message) {
+                Ok(Some(command)) => run_command(command, peer, &state, &mut session).await,
+                Ok(None) => {}
+                Err(e) => eprintln!("Ignoring OSC message {} from {}: {}", message.address, peer, e),
+            }
+        }
+    }
+}
+
+async fn run_command(command: OscCommand, peer: SocketAddr, state: &AppState, session: &mut Session) {
+    let command = match command {
+        OscCommand::Trigger(combo) => Command::RemoteTrigger(TriggerRequest { combo: Some(combo), binding: None }),
+        OscCommand::Binding(name) => Command::RemoteTrigger(TriggerRequest { combo: None, binding: Some(name) }),
+        OscCommand::Profile(profile) => Command::SwitchProfile { profile },
+    };
+    match commands::handle_command(command, state, session).await {
+        Reply::Triggered { combo, .. } => println!("OSC trigger from {}: {}", peer, combo),
+        Reply::ProfileSwitched { profile, .. } => println!("OSC from {} switched to profile: {}", peer, profile),
+        Reply::Error { message, .. } => eprintln!("OSC from {}: {}", peer, message),
+        _ => {}
+    }
+}
+
+/// `Ok(None)` for messages that aren't meant to do anything, like a button's release.
+fn parse_command(message: &OscMessage) -> Result<Option<OscCommand>, String> {
+    let path = message.address.strip_prefix(ADDRESS_PREFIX).ok_or("not an /audioimporter/ address")?;
+    let (verb, path_name) = match path.split_once('/') {
+        Some((verb, name)) => (verb, Some(name)),
+        None => (path, None),
+    };
+
+    let numbers: Vec<f64> = message
+        .args
+        .iter()
+        .filter_map(|arg| match arg {
+            OscArg::Number(number) => Some(*number),
+            _ => None,
+        })
+        .collect();
+    if !numbers.is_empty() && numbers.iter().all(|number| *number == 0.0) {
+        return Ok(None);
+    }
+
+    let string_arg = message.args.iter().find_map(|arg| match arg {
+        OscArg::String(name) => Some(name.as_str()),
+        _ => None,
+    });
+    let name = string_arg.or(path_name).filter(|name| !name.is_empty()).ok_or("missing a name")?.to_string();
+
+    match verb {
+        "trigger" => Ok(Some(OscCommand::Trigger(name))),
+        "binding" => Ok(Some(OscCommand::Binding(name))),
+        "profile" => Ok(Some(OscCommand::Profile(name))),
+        _ => Err(format!("unknown command {}", verb)),
+    }
+}
+
+/// The messages of a packet, in order; bundles are flattened and their time tags ignored.
+fn decode_packet(packet: &[u8]) -> Result<Vec<OscMessage>, String> {
+    let mut reader = Reader { bytes: packet, position: 0 };
+    if packet.starts_with(b"#bundle\0") {
+        reader.take(16)?;
+        let mut messages = Vec::new();
+        while reader.position < packet.len() {
+            let length = reader.int()?;
+            let length = usize::try_from(length).map_err(|_| "negative bundle element size")?;
+            messages.extend(decode_packet(reader.take(length)?)?);
+        }
+        return Ok(messages);
+    }
+
+    let address = reader.string()?;
+    if !address.starts_with('/') {
+        return Err("address doesn't start with /".to_string());
+    }
+    // Some old senders leave the type tags out; there's nothing to read then
+    let tags = if reader.position < packet.len() { reader.string()? } else { ",".to_string() };
+    let tags = tags.strip_prefix(',').ok_or("type tags don't start with ,")?;
+
+    let mut args = Vec::new();
+    for tag in tags.chars() {
+        let arg = match tag {
+            's' | 'S' => OscArg::String(reader.string()?),
+            'i' => OscArg::Number(f64::from(reader.int()?)),
+            'f' => OscArg::Number(f64::from(f32::from_bits(reader.int()? as u32))),
+            'd' => OscArg::Number(f64::from_bits(u64::from_be_bytes(reader.array()?))),
+            'h' => OscArg::Number(i64::from_be_bytes(reader.array()?) as f64),
+            'T' => OscArg::Number(1.0),
+            'F' => OscArg::Number(0.0),
+            't' => {
+                reader.take(8)?;
+                OscArg::Other
+            }
+            'c' | 'r' | 'm' => {
+                reader.take(4)?;
+                OscArg::Other
+            }
+            'b' => {
+                let length = usize::try_from(reader.int()?).map_err(|_| "negative blob size")?;
+                reader.take(length.next_multiple_of(4))?;
+                OscArg::Other
+            }
+            'N' | 'I' | '[' | ']' => OscArg::Other,
+            _ => return Err(format!("unknown type tag {}", tag)),
+        };
+        args.push(arg);
+    }
+    Ok(vec![OscMessage { address, args }])
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let bytes = self.bytes.get(self.position..self.position + length).ok_or("packet ends early")?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn int(&mut self) -> Result<i32, String> {
+        Ok(i32::from_be_bytes(self.array()?))
+    }
+
+    /// A NUL-terminated string padded with NULs to a multiple of 4 bytes.
+    fn string(&mut self) -> Result<String, String> {
+        let rest = &self.bytes[self.position..];
+        let length = rest.iter().position(|byte| *byte == 0).ok_or("unterminated string")?;
+        let text = std::str::from_utf8(&rest[..length]).map_err(|_| "string isn't UTF-8")?.to_string();
+        self.take((length + 1).next_multiple_of(4))?;
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AudioBinding, Config};
+    use crate::input::InputEvent;
+    use std::time::Duration;
+
+    enum Arg<'a> {
+        S(&'a str),
+        I(i32),
+        F(f32),
+        T,
+    }
+
+    fn padded(bytes: &[u8]) -> Vec<u8> {
+        let mut padded = bytes.to_vec();
+        padded.push(0);
+        padded.resize(padded.len().next_multiple_of(4), 0);
+        padded
+    }
+
+    fn message(address: &str, args: &[Arg]) -> Vec<u8> {
+        let tags: String = args
+            .iter()
+            .map(|arg| match arg {
+                Arg::S(_) => 's',
+                Arg::I(_) => 'i',
+                Arg::F(_) => 'f',
+                Arg::T => 'T',
+            })
+            .collect();
+        let mut bytes = padded(address.as_bytes());
+        bytes.extend(padded(format!(",{}", tags).as_bytes()));
+        for arg in args {
+            match arg {
+                Arg::S(text) => bytes.extend(padded(text.as_bytes())),
+                Arg::I(number) => bytes.extend(number.to_be_bytes()),
+                Arg::F(number) => bytes.extend(number.to_bits().to_be_bytes()),
+                Arg::T => {}
+            }
+        }
+        bytes
+    }
+
+    fn bundle(elements: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = padded(b"#bundle");
+        bytes.extend(1u64.to_be_bytes());
+        for element in elements {
+            bytes.extend((element.len() as i32).to_be_bytes());
+            bytes.extend(element);
+        }
+        bytes
+    }
+
+    fn command(packet: &[u8]) -> Result<Option<OscCommand>, String> {
+        let messages = decode_packet(packet)?;
+        assert_eq!(messages.len(), 1);
+        parse_command(&messages[0])
+    }
+
+    #[test]
+    fn arguments_are_decoded() {
+        let packet = message("/audioimporter/trigger", &[Arg::S("Ctrl+1"), Arg::I(-3), Arg::F(0.5), Arg::T]);
+        let messages = decode_packet(&packet).unwrap();
+        assert_eq!(
+            messages,
+            [OscMessage {
+                address: "/audioimporter/trigger".to_string(),
+                args: vec![
+                    OscArg::String("Ctrl+1".to_string()),
+                    OscArg::Number(-3.0),
+                    OscArg::Number(0.5),
+                    OscArg::Number(1.0),
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn bundles_are_flattened_in_order() {
+        let packet = bundle(&[
+            message("/audioimporter/profile", &[Arg::S("Vlog")]),
+            bundle(&[message("/audioimporter/binding/whoosh", &[])]),
+        ]);
+        let addresses: Vec<String> = decode_packet(&packet).unwrap().into_iter().map(|m| m.address).collect();
+        assert_eq!(addresses, ["/audioimporter/profile", "/audioimporter/binding/whoosh"]);
+    }
+
+    #[test]
+    fn message_without_type_tags_has_no_arguments() {
+        let packet = padded(b"/audioimporter/binding/whoosh");
+        assert_eq!(command(&packet), Ok(Some(OscCommand::Binding("whoosh".to_string()))));
+    }
+
+    #[test]
+    fn malformed_packets_are_refused() {
+        assert!(decode_packet(b"audioimporter\0\0\0").is_err());
+        assert!(decode_packet(&padded(b"/a")[..2]).is_err());
+        let mut truncated = message("/audioimporter/trigger", &[Arg::I(1)]);
+        truncated.truncate(truncated.len() - 2);
+        assert_eq!(decode_packet(&truncated), Err("packet ends early".to_string()));
+        let mut unknown = padded(b"/a");
+        unknown.extend(padded(b",q"));
+        assert_eq!(decode_packet(&unknown), Err("unknown type tag q".to_string()));
+    }
+
+    #[test]
+    fn name_comes_from_a_string_argument_or_the_address() {
+        let trigger = message("/audioimporter/trigger", &[Arg::S("Ctrl+1")]);
+        assert_eq!(command(&trigger), Ok(Some(OscCommand::Trigger("Ctrl+1".to_string()))));
+        let profile = message("/audioimporter/profile/Vlog", &[Arg::F(1.0)]);
+        assert_eq!(command(&profile), Ok(Some(OscCommand::Profile("Vlog".to_string()))));
+    }
+
+    #[test]
+    fn button_release_does_nothing() {
+        let release = message("/audioimporter/binding/whoosh", &[Arg::F(0.0)]);
+        assert_eq!(command(&release), Ok(None));
+    }
+
+    #[test]
+    fn unknown_or_incomplete_messages_are_errors() {
+        assert!(command(&message("/other/trigger", &[Arg::S("Ctrl+1")])).is_err());
+        assert_eq!(command(&message("/audioimporter/trigger", &[])), Err("missing a name".to_string()));
+        assert_eq!(
+            command(&message("/audioimporter/launch", &[Arg::S("x")])),
+            Err("unknown command launch".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn trigger_sent_over_udp_fires_the_binding() {
+        let mut config = Config::default();
+        config.upsert_profile("Default", [("Ctrl+1".to_string(), AudioBinding::for_file("/sfx/whoosh.wav"))].into());
+        config.normalize();
+        let state = AppState::for_config(config);
+        let mut triggers = state.input.subscribe();
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = socket.local_addr().unwrap();
+        tokio::spawn(serve(socket, state));
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.send_to(&message("/audioimporter/binding/whoosh", &[Arg::F(1.0)]), address).await.unwrap();
+
+        let trigger = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Ok(InputEvent::Trigger(trigger)) = triggers.recv().await {
+                    return trigger;
+                }
+            }
+        })
+        .await
+        .expect("the OSC message triggers the binding");
+        assert_eq!(trigger.combo, "Ctrl+1");
+        assert_eq!(trigger.binding.path, "/sfx/whoosh.wav");
+    }
+}
@@ -0,0 +1,105 @@
+use crate::cli::CliOptions;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+const APP_DIR_NAME: &str = "AudioImporter";
+const CONFIG_FILE_NAME: &str = "config.json";
+const DISCOVERY_FILE_NAME: &str = "server.json";
+// Dropping this file next to the executable turns on portable mode without any flag
+const PORTABLE_MARKER: &str = "portable.txt";
+
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Resolves where `config.json` lives for this run and creates its directory.
+/// Order: `--config` / `AUDIO_IMPORTER_CONFIG`, portable mode, then the per-OS default.
+pub fn init_config_path(options: &CliOptions) -> io::Result<PathBuf> {
+    let config_path = match &options.config {
+        Some(path) if path.is_dir() => path.join(CONFIG_FILE_NAME),
+        Some(path) => path.clone(),
+        None if options.portable || portable_marker_exists() => portable_config_path()?,
+        None => match default_config_path() {
+            Some(path) => {
+                adopt_legacy_linux_config(&path);
+                path
+            }
+            None => {
+                eprintln!("Could not determine the per-user config directory, falling back to portable mode");
+                portable_config_path()?
+            }
+        },
+    };
+
+    if let Some(parent) = config_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+
+    Ok(CONFIG_PATH.get_or_init(|| config_path).clone())
+}
+
+pub fn get_config_path() -> PathBuf {
+    CONFIG_PATH
+        .get_or_init(|| default_config_path().unwrap_or_else(|| PathBuf::from(CONFIG_FILE_NAME)))
+        .clone()
+}
+
+/// Where the running server announces its address: the per-user app directory even with `--config`
+/// or portable mode, since that is the one place the panels know to look.
+pub fn discovery_path() -> PathBuf {
+    default_config_path().unwrap_or_else(get_config_path).with_file_name(DISCOVERY_FILE_NAME)
+}
+
+fn default_config_path() -> Option<PathBuf> {
+    let config_dir = if cfg!(target_os = "windows") {
+        env_path("APPDATA").or_else(|| env_path("USERPROFILE").map(|home| home.join("AppData").join("Roaming")))?
+    } else if cfg!(target_os = "macos") {
+        env_path("HOME")?.join("Library").join("Application Support")
+    } else {
+        // XDG Base Directory spec: $XDG_CONFIG_HOME, else ~/.config
+        env_path("XDG_CONFIG_HOME")
+            .filter(|p| p.is_absolute())
+            .or_else(|| env_path("HOME").map(|home| home.join(".config")))?
+    };
+
+    Some(config_dir.join(APP_DIR_NAME).join(CONFIG_FILE_NAME))
+}
+
+// Linux builds used to write to the macOS location; carry that file over the first time
+fn adopt_legacy_linux_config(config_path: &Path) {
+    if !cfg!(target_os = "linux") || config_path.exists() {
+        return;
+    }
+    let Some(home) = env_path("HOME") else { return };
+    let legacy = home.join("Library").join("Application Support").join(APP_DIR_NAME).join(CONFIG_FILE_NAME);
+    if !legacy.is_file() {
+        return;
+    }
+
+    let copied = config_path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::copy(&legacy, config_path));
+    match copied {
+        Ok(_) => println!("Copied config from {} to {}", legacy.display(), config_path.display()),
+        Err(e) => eprintln!("Failed to copy legacy config {}: {}", legacy.display(), e),
+    }
+}
+
+fn portable_config_path() -> io::Result<PathBuf> {
+    Ok(exe_dir()?.join("data").join(CONFIG_FILE_NAME))
+}
+
+fn portable_marker_exists() -> bool {
+    exe_dir().map(|dir| dir.join(PORTABLE_MARKER).is_file()).unwrap_or(false)
+}
+
+fn exe_dir() -> io::Result<PathBuf> {
+    let exe = env::current_exe()?;
+    Ok(exe.parent().map(PathBuf::from).unwrap_or_default())
+}
+
+fn env_path(name: &str) -> Option<PathBuf> {
+    env::var_os(name).filter(|v| !v.is_empty()).map(PathBuf::from)
+}
@@ -0,0 +1,341 @@
+//! Premiere Pro's keyboard shortcuts, read from a `.kys` file exported from Premiere's Keyboard
+//! Shortcuts dialog or taken from the built-in defaults, so bindings that would also fire a
+//! Premiere command can be flagged.
+
+use crate::config::ConfigError;
+use std::fs;
+use std::path::Path;
+
+/// One Premiere command and the combo that runs it, spelled like our bindings (`Ctrl+Shift+Z`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PremiereShortcut {
+    pub command: String,
+    pub combo: String,
+}
+
+/// Premiere's default Windows shortcuts that a binding is likely to hit. macOS swaps Ctrl for Cmd.
+const DEFAULT_SHORTCUTS: &[(&str, &str)] = &[
+    ("Ctrl+N", "New Sequence"),
+    ("Ctrl+Alt+N", "New Project"),
+    ("Ctrl+O", "Open Project"),
+    ("Ctrl+W", "Close Panel"),
+    ("Ctrl+Shift+W", "Close Project"),
+    ("Ctrl+S", "Save"),
+    ("Ctrl+Shift+S", "Save As"),
+    ("Ctrl+Alt+S", "Save a Copy"),
+    ("Ctrl+I", "Import"),
+    ("Ctrl+M", "Export Media"),
+    ("Ctrl+Q", "Exit"),
+    ("Ctrl+Z", "Undo"),
+    ("Ctrl+Shift+Z", "Redo"),
+    ("Ctrl+X", "Cut"),
+    ("Ctrl+C", "Copy"),
+    ("Ctrl+V", "Paste"),
+    ("Ctrl+Shift+V", "Paste Insert"),
+    ("Ctrl+Alt+V", "Paste Attributes"),
+    ("Ctrl+A", "Select All"),
+    ("Ctrl+Shift+A", "Deselect All"),
+    ("Ctrl+F", "Find"),
+    ("Ctrl+E", "Edit Original"),
+    ("Ctrl+D", "Apply Video Transition"),
+    ("Ctrl+Shift+D", "Apply Audio Transition"),
+    ("Ctrl+K", "Add Edit"),
+    ("Ctrl+Shift+K", "Add Edit to All Tracks"),
+    ("Ctrl+L", "Link"),
+    ("Ctrl+G", "Group"),
+    ("Ctrl+Shift+G", "Ungroup"),
+    ("Ctrl+R", "Speed/Duration"),
+    ("Ctrl+U", "Make Subclip"),
+    ("Ctrl+B", "New Bin"),
+    ("Ctrl+Shift+I", "Clear In"),
+    ("Ctrl+Shift+O", "Clear Out"),
+    ("Ctrl+Shift+X", "Clear In and Out"),
+    ("Shift+1", "Project Panel"),
+    ("Shift+2", "Source Monitor"),
+    ("Shift+3", "Timelines"),
+    ("Shift+4", "Program Monitor"),
+    ("Shift+5", "Effect Controls"),
+    ("Shift+6", "Audio Track Mixer"),
+    ("Shift+7", "Effects"),
+    ("Shift+8", "Media Browser"),
+    ("Shift+9", "Audio Clip Mixer"),
+    ("Shift+I", "Go to In"),
+    ("Shift+O", "Go to Out"),
+    ("Shift+Delete", "Ripple Delete"),
+    ("Alt+Delete", "Ripple Delete"),
+    ("Space", "Play/Stop"),
+    ("J", "Shuttle Left"),
+    ("K", "Shuttle Stop"),
+    ("L", "Shuttle Right"),
+    ("I", "Mark In"),
+    ("O", "Mark Out"),
+    ("X", "Mark Clip"),
+    ("M", "Add Marker"),
+    ("Q", "Ripple Trim Previous Edit to Playhead"),
+    ("W", "Ripple Trim Next Edit to Playhead"),
+    ("A", "Track Select Forward Tool"),
+    ("B", "Ripple Edit Tool"),
+    ("C", "Razor Tool"),
+    ("H", "Hand Tool"),
+    ("N", "Rolling Edit Tool"),
+    ("P", "Pen Tool"),
+    ("R", "Rate Stretch Tool"),
+    ("T", "Type Tool"),
+    ("U", "Slide Tool"),
+    ("V", "Selection Tool"),
+    ("Y", "Slip Tool"),
+    ("Z", "Zoom Tool"),
+    ("Comma", "Insert"),
+    ("Dot", "Overwrite"),
+    ("Equal", "Zoom In"),
+    ("Minus", "Zoom Out"),
+    ("BackSlash", "Zoom to Sequence"),
+    ("Up", "Go to Previous Edit Point"),
+    ("Down", "Go to Next Edit Point"),
+    ("Left", "Step Back"),
+    ("Right", "Step Forward"),
+    ("Home", "Go to Sequence Start"),
+    ("End", "Go to Sequence End"),
+    ("Delete", "Clear"),
+];
+
+/// Premiere's shortcuts when no `.kys` file is configured.
+pub fn default_shortcuts() -> Vec<PremiereShortcut> {
+    DEFAULT_SHORTCUTS
+        .iter()
+        .map(|(combo, command)| PremiereShortcut {
+            command: command.to_string(),
+            combo: combo.split('+').map(modifier_name).collect::<Vec<_>>().join("+"),
+        })
+        .collect()
+}
+
+/// Reads a `.kys` file. Premiere writes them as UTF-8 or, in older versions, UTF-16.
+pub fn load_kys(path: &Path) -> Result<Vec<PremiereShortcut>, ConfigError> {
+    let invalid = |reason: String| ConfigError::InvalidShortcutFile { path: path.to_path_buf(), reason };
+    let bytes = fs::read(path).map_err(|e| invalid(e.to_string()))?;
+    let xml = decode(&bytes).ok_or_else(|| invalid("not UTF-8 or UTF-16 text".to_string()))?;
+    parse_kys(&xml).map_err(invalid)
+}
+
+fn decode(bytes: &[u8]) -> Option<String> {
+    let utf16 = |bytes: &[u8], from_bytes: fn([u8; 2]) -> u16| {
+        let units: Vec<u16> = bytes.chunks_exact(2).map(|pair| from_bytes([pair[0], pair[1]])).collect();
+        String::from_utf16(&units).ok()
+    };
+    match bytes {
+        [0xFF, 0xFE, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        [0xFE, 0xFF, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        [0xEF, 0xBB, 0xBF, rest @ ..] => String::from_utf8(rest.to_vec()).ok(),
+        _ => String::from_utf8(bytes.to_vec()).ok(),
+    }
+}
+
+#[derive(Default)]
+struct Element {
+    name: String,
+    text: String,
+    /// Text of the leaf children, e.g. `commandname` and `virtualkey` of a shortcut item.
+    fields: Vec<(String, String)>,
+}
+
+/// Collects every element that has a `commandname` and a `virtualkey` child, wherever it sits
+/// (`<context.global><item.0>...`). Modifiers are `modifier.ctrl`, `modifier.alt` and
+/// `modifier.shift` children set to `true`.
+pub fn parse_kys(xml: &str) -> Result<Vec<PremiereShortcut>, String> {
+    let mut shortcuts = Vec::new();
+    let mut stack = vec![Element::default()];
+    let mut rest = xml;
+
+    while let Some(start) = rest.find('<') {
+        if let Some(element) = stack.last_mut() {
+            element.text.push_str(&rest[..start]);
+        }
+        let end = rest[start..].find('>').ok_or("unterminated tag")? + start;
+        let tag = &rest[start + 1..end];
+        rest = &rest[end + 1..];
+
+        if tag.starts_with('?') || tag.starts_with('!') || tag.ends_with('/') {
+            continue;
+        }
+        if let Some(name) = tag.strip_prefix('/') {
+            let element = stack.pop().filter(|e| e.name == name.trim()).ok_or_else(|| format!("unexpected </{}>", name))?;
+            let parent = stack.last_mut().ok_or("unbalanced tags")?;
+            if element.fields.is_empty() {
+                parent.fields.push((element.name, unescape(element.text.trim())));
+            } else if let Some(shortcut) = shortcut(&element.fields) {
+                shortcuts.push(shortcut);
+            }
+        } else {
+            let name = tag.split_whitespace().next().unwrap_or_default();
+            stack.push(Element { name: name.to_string(), ..Element::default() });
+        }
+    }
+
+    if stack.len() != 1 {
+        return Err("unclosed tags at end of file".to_string());
+    }
+    if shortcuts.is_empty() {
+        return Err("no keyboard shortcuts found".to_string());
+    }
+    Ok(shortcuts)
+}
+
+fn shortcut(fields: &[(String, String)]) -> Option<PremiereShortcut> {
+    let field = |name: &str| fields.iter().find(|(field, _)| field == name).map(|(_, value)| value.as_str());
+    let command = field("commandname")?;
+    let key = virtual_key_name(field("virtualkey")?.parse().ok()?)?;
+
+    let mut keys: Vec<String> = [("modifier.ctrl", "Ctrl"), ("modifier.alt", "Alt"), ("modifier.shift", "Shift")]
+        .iter()
+        .filter(|(name, _)| field(name) == Some("true"))
+        .map(|(_, modifier)| modifier_name(modifier).to_string())
+        .collect();
+    keys.push(key);
+    Some(PremiereShortcut { command: command.to_string(), combo: keys.join("+") })
+}
+
+/// Premiere's Ctrl is Cmd on macOS.
+fn modifier_name(key: &str) -> &str {
+    match key {
+        "Ctrl" if cfg!(target_os = "macos") => "Cmd",
+        key => key,
+    }
+}
+
+/// The key name our input sources use for a Windows virtual-key code, which is what `.kys` files store.
+fn virtual_key_name(code: u32) -> Option<String> {
+    let name = match code {
+        0x30..=0x39 | 0x41..=0x5A => char::from_u32(code)?.to_string(),
+        0x60..=0x69 => format!("Numpad{}", code - 0x60),
+        0x70..=0x87 => format!("F{}", code - 0x6F),
+        0x08 => "Backspace".to_string(),
+        0x09 => "Tab".to_string(),
+        0x0D => "Enter".to_string(),
+        0x1B => "Esc".to_string(),
+        0x20 => "Space".to_string(),
+        0x21 => "PageUp".to_string(),
+        0x22 => "PageDown".to_string(),
+        0x23 => "End".to_string(),
+        0x24 => "Home".to_string(),
+        0x25 => "Left".to_string(),
+        0x26 => "Up".to_string(),
+        0x27 => "Right".to_string(),
+        0x28 => "Down".to_string(),
+        0x2D => "Insert".to_string(),
+        0x2E => "Delete".to_string(),
+        0x6A => "NumpadMultiply".to_string(),
+        0x6B => "NumpadAdd".to_string(),
+        0x6D => "NumpadSubtract".to_string(),
+        0x6E => "NumpadDecimal".to_string(),
+        0x6F => "NumpadDivide".to_string(),
+        0xBA => "Semicolon".to_string(),
+        0xBB => "Equal".to_string(),
+        0xBC => "Comma".to_string(),
+        0xBD => "Minus".to_string(),
+        0xBE => "Dot".to_string(),
+        0xBF => "Slash".to_string(),
+        0xC0 => "Grave".to_string(),
+        0xDB => "LeftBracket".to_string(),
+        0xDC => "BackSlash".to_string(),
+        0xDD => "RightBracket".to_string(),
+        0xDE => "Apostrophe".to_string(),
+        _ => return None,
+    };
+    Some(name)
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KYS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!-- Exported from Premiere Pro -->
+<PremiereData Version="3">
+  <shortcuts>
+    <context.global>
+      <item.0>
+        <commandname>cmd.file.save</commandname>
+        <virtualkey>83</virtualkey>
+        <modifier.ctrl>true</modifier.ctrl>
+        <modifier.alt>false</modifier.alt>
+        <modifier.shift>false</modifier.shift>
+      </item.0>
+      <item.1>
+        <commandname>Ripple &amp; Delete</commandname>
+        <virtualkey>46</virtualkey>
+        <modifier.shift>true</modifier.shift>
+      </item.1>
+      <item.2>
+        <commandname>cmd.numpad</commandname>
+        <virtualkey>97</virtualkey>
+        <modifier.alt>true</modifier.alt>
+      </item.2>
+      <item.3>
+        <commandname>cmd.unknownkey</commandname>
+        <virtualkey>255</virtualkey>
+      </item.3>
+      <empty/>
+    </context.global>
+  </shortcuts>
+</PremiereData>"#;
+
+    fn ctrl() -> &'static str {
+        modifier_name("Ctrl")
+    }
+
+    #[test]
+    fn shortcuts_are_read_with_their_modifiers() {
+        let shortcuts = parse_kys(KYS).unwrap();
+        let combos: Vec<(&str, &str)> = shortcuts.iter().map(|s| (s.command.as_str(), s.combo.as_str())).collect();
+        assert_eq!(
+            combos,
+            [
+                ("cmd.file.save", format!("{}+S", ctrl()).as_str()),
+                ("Ripple & Delete", "Shift+Delete"),
+                ("cmd.numpad", "Alt+Numpad1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn file_without_shortcuts_is_an_error() {
+        assert_eq!(parse_kys("<PremiereData></PremiereData>"), Err("no keyboard shortcuts found".to_string()));
+    }
+
+    #[test]
+    fn malformed_xml_is_an_error() {
+        assert!(parse_kys("<a><b></a>").is_err());
+        assert!(parse_kys("<a><b>").is_err());
+        assert!(parse_kys("<a").is_err());
+    }
+
+    #[test]
+    fn utf16_files_are_decoded() {
+        let text = "<a>é</a>";
+        let mut le = vec![0xFF, 0xFE];
+        le.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        let mut be = vec![0xFE, 0xFF];
+        be.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+        let bom = [&[0xEF, 0xBB, 0xBF][..], text.as_bytes()].concat();
+
+        for bytes in [le, be, bom, text.as_bytes().to_vec()] {
+            assert_eq!(decode(&bytes).as_deref(), Some(text));
+        }
+        assert_eq!(decode(&[0xC3, 0x28]), None);
+    }
+
+    #[test]
+    fn defaults_follow_the_platform_modifier() {
+        let save = default_shortcuts().into_iter().find(|s| s.command == "Save").unwrap();
+        assert_eq!(save.combo, format!("{}+S", ctrl()));
+    }
+}
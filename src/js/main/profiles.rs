@@ -0,0 +1,259 @@
+//! Profile management on the in-memory `Config`. Callers hold the config lock and save afterwards,
+//! so each operation lands on disk as a single write.
+
+use crate::config::{validate_new_profile_name, Config, ConfigError, NormalizationPolicy, ProfileConfig};
+use std::collections::{HashMap, HashSet};
+
+impl Config {
+    /// Finds an existing profile whose name differs from `name` only by case.
+    fn find_conflicting_name(&self, name: &str) -> Option<&String> {
+        self.profiles.keys().find(|existing| existing.to_lowercase() == name.to_lowercase())
+    }
+
+    fn check_new_name(&self, name: &str) -> Result<(), ConfigError> {
+        validate_new_profile_name(name)?;
+        match self.find_conflicting_name(name) {
+            Some(existing) => Err(ConfigError::ProfileExists(existing.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Inserts or replaces a profile, appending new ones to the end of the order.
+    pub fn upsert_profile(&mut self, name: &str, bindings: ProfileConfig) {
+        if self.profiles.insert(name.to_string(), bindings).is_none() {
+            self.profile_order.push(name.to_string());
+        }
+    }
+
+    /// Replaces a profile's bindings, creating it under the same rules as `create_profile` when
+    /// it doesn't exist yet.
+    pub fn save_profile(&mut self, name: &str, bindings: ProfileConfig) -> Result<(), ConfigError> {
+        if !self.profiles.contains_key(name) {
+            self.check_new_name(name)?;
+        }
+        self.upsert_profile(name, bindings);
+        Ok(())
+    }
+
+    pub fn create_profile(&mut self, name: &str) -> Result<(), ConfigError> {
+        self.check_new_name(name)?;
+        self.upsert_profile(name, ProfileConfig::new());
+        self.normalize();
+        Ok(())
+    }
+
+    pub fn delete_profile(&mut self, name: &str) -> Result<(), ConfigError> {
+        if self.profiles.remove(name).is_none() {
+            return Err(ConfigError::ProfileNotFound(name.to_string()));
+        }
+        // If the deleted profile was the current profile, reset currentProfile
+        if self.current_profile.as_deref() == Some(name) {
+            self.current_profile = None;
+        }
+        self.profile_order.retain(|n| n != name);
+        self.profile_settings.remove(name);
+        Ok(())
+    }
+
+    /// Renames a profile in place, keeping its position and carrying the current/last-selected markers over.
+    pub fn rename_profile(&mut self, from: &str, to: &str) -> Result<(), ConfigError> {
+        if !self.profiles.contains_key(from) {
+            return Err(ConfigError::ProfileNotFound(from.to_string()));
+        }
+        validate_new_profile_name(to)?;
+        // Changing only the case of a name is fine; colliding with another profile is not
+        if let Some(existing) = self.find_conflicting_name(to).filter(|existing| existing.as_str() != from) {
+            return Err(ConfigError::ProfileExists(existing.clone()));
+        }
+
+        let bindings = self.profiles.remove(from).unwrap();
+        self.profiles.insert(to.to_string(), bindings);
+        if let Some(settings) = self.profile_settings.remove(from) {
+            self.profile_settings.insert(to.to_string(), settings);
+        }
+        for name in self.profile_order.iter_mut().filter(|n| n.as_str() == from) {
+            *name = to.to_string();
+        }
+        for marker in [&mut self.current_profile, &mut self.last_selected_profile] {
+            if marker.as_deref() == Some(from) {
+                *marker = Some(to.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies a profile's bindings and settings under a new name, placed right after the original.
+    pub fn duplicate_profile(&mut self, from: &str, to: &str) -> Result<(), ConfigError> {
+        let bindings = self
+            .profiles
+            .get(from)
+            .cloned()
+            .ok_or_else(|| ConfigError::ProfileNotFound(from.to_string()))?;
+        self.check_new_name(to)?;
+
+        self.profiles.insert(to.to_string(), bindings);
+        if let Some(settings) = self.profile_settings.get(from).cloned() {
+            self.profile_settings.insert(to.to_string(), settings);
+        }
+        let position = self.profile_order.iter().position(|n| n == from).map_or(self.profile_order.len(), |i| i + 1);
+        self.profile_order.insert(position, to.to_string());
+        Ok(())
+    }
+
+    /// Replaces the display order. `order` must be a permutation of the existing profile names.
+    pub fn reorder_profiles(&mut self, order: Vec<String>) -> Result<(), ConfigError> {
+        let unique: HashSet<&String> = order.iter().collect();
+        let is_permutation = order.len() == self.profiles.len()
+            && unique.len() == order.len()
+            && order.iter().all(|name| self.profiles.contains_key(name));
+        if !is_permutation {
+            return Err(ConfigError::InvalidProfileOrder);
+        }
+
+        self.profile_order = order;
+        Ok(())
+    }
+
+    /// Switches a profile to another normalization policy and rewrites its combos to match.
+    /// Nothing changes if two bindings with different sounds would end up on the same combo.
+    pub fn set_normalization(&mut self, profile: &str, policy: NormalizationPolicy) -> Result<(), ConfigError> {
+        policy.validate()?;
+        let bindings = self.profiles.get(profile).ok_or_else(|| ConfigError::ProfileNotFound(profile.to_string()))?;
+
+        let mut migrated = ProfileConfig::new();
+        let mut migrated_from: HashMap<String, &String> = HashMap::new();
+        for (combo, binding) in bindings {
+            for new_combo in policy.renormalize(combo) {
+                // `LeftCtrl+1` and `RightCtrl+1` may merge back into `Ctrl+1` if they play the same sound
+                if migrated.get(&new_combo).is_some_and(|existing| existing != binding) {
+                    return Err(ConfigError::ComboCollision {
+                        combo: new_combo.clone(),
+                        first: migrated_from[&new_combo].clone(),
+                        second: combo.clone(),
+                    });
+                }
+                migrated.insert(new_combo.clone(), binding.clone());
+                migrated_from.insert(new_combo, combo);
+            }
+        }
+
+        self.profiles.insert(profile.to_string(), migrated);
+        self.profile_settings.entry(profile.to_string()).or_default().normalization = policy;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AudioBinding, ProfileSettings};
+
+    /// A config with `names` as profiles, in that order.
+    fn config(names: &[&str]) -> Config {
+        let mut config = Config::default();
+        for name in names {
+            config.create_profile(name).unwrap();
+        }
+        config
+    }
+
+    fn bindings(entries: &[(&str, &str)]) -> ProfileConfig {
+        entries.iter().map(|(combo, path)| (combo.to_string(), AudioBinding::for_file(path))).collect()
+    }
+
+    #[test]
+    fn created_profiles_go_last_and_names_are_unique_ignoring_case() {
+        let mut config = config(&["Vlog", "Podcast"]);
+        assert_eq!(config.profile_order, ["Vlog", "Podcast"]);
+        assert!(matches!(config.create_profile("vlog"), Err(ConfigError::ProfileExists(existing)) if existing == "Vlog"));
+        assert!(matches!(config.create_profile(" Interview"), Err(ConfigError::InvalidProfileName(_))));
+        assert!(matches!(config.create_profile("Inter\nview"), Err(ConfigError::InvalidProfileName(_))));
+    }
+
+    #[test]
+    fn saving_a_new_profile_checks_its_name() {
+        let mut config = config(&["Vlog"]);
+        assert!(matches!(config.save_profile("vlog", ProfileConfig::new()), Err(ConfigError::ProfileExists(_))));
+        assert!(matches!(config.save_profile("Trailing ", ProfileConfig::new()), Err(ConfigError::InvalidProfileName(_))));
+        assert_eq!(config.profile_order, ["Vlog"]);
+
+        config.save_profile("Podcast", bindings(&[("Ctrl+1", "/sfx/a.wav")])).unwrap();
+        assert_eq!(config.profile_order, ["Vlog", "Podcast"]);
+    }
+
+    #[test]
+    fn saving_an_existing_profile_replaces_its_bindings() {
+        let mut config = config(&["Vlog"]);
+        config.save_profile("Vlog", bindings(&[("Ctrl+1", "/sfx/a.wav")])).unwrap();
+        config.save_profile("Vlog", bindings(&[("Ctrl+2", "/sfx/b.wav")])).unwrap();
+        assert_eq!(config.profiles["Vlog"], bindings(&[("Ctrl+2", "/sfx/b.wav")]));
+        assert_eq!(config.profile_order, ["Vlog"]);
+    }
+
+    #[test]
+    fn rename_keeps_position_settings_and_markers() {
+        let mut config = config(&["Vlog", "Podcast", "Interview"]);
+        config.current_profile = Some("Podcast".to_string());
+        config.last_selected_profile = Some("Podcast".to_string());
+        config.profile_settings.insert("Podcast".to_string(), ProfileSettings::default());
+
+        config.rename_profile("Podcast", "Radio").unwrap();
+        assert_eq!(config.profile_order, ["Vlog", "Radio", "Interview"]);
+        assert!(config.profiles.contains_key("Radio") && !config.profiles.contains_key("Podcast"));
+        assert!(config.profile_settings.contains_key("Radio"));
+        assert_eq!(config.current_profile.as_deref(), Some("Radio"));
+        assert_eq!(config.last_selected_profile.as_deref(), Some("Radio"));
+    }
+
+    #[test]
+    fn rename_may_change_case_but_not_collide() {
+        let mut config = config(&["Vlog", "Podcast"]);
+        config.rename_profile("Vlog", "VLOG").unwrap();
+        assert_eq!(config.profile_order, ["VLOG", "Podcast"]);
+        assert!(matches!(config.rename_profile("VLOG", "podcast"), Err(ConfigError::ProfileExists(_))));
+        assert!(matches!(config.rename_profile("Missing", "Other"), Err(ConfigError::ProfileNotFound(_))));
+    }
+
+    #[test]
+    fn duplicate_goes_right_after_the_original_with_its_bindings() {
+        let mut config = config(&["Vlog", "Podcast"]);
+        config.save_profile("Vlog", bindings(&[("Ctrl+1", "/sfx/a.wav")])).unwrap();
+        config.duplicate_profile("Vlog", "Vlog copy").unwrap();
+        assert_eq!(config.profile_order, ["Vlog", "Vlog copy", "Podcast"]);
+        assert_eq!(config.profiles["Vlog copy"], config.profiles["Vlog"]);
+        assert!(matches!(config.duplicate_profile("Vlog", "podcast"), Err(ConfigError::ProfileExists(_))));
+    }
+
+    #[test]
+    fn reorder_needs_every_profile_exactly_once() {
+        let mut config = config(&["Vlog", "Podcast"]);
+        let order = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        for bad in [order(&["Vlog"]), order(&["Vlog", "Vlog"]), order(&["Vlog", "Radio"])] {
+            assert!(matches!(config.reorder_profiles(bad), Err(ConfigError::InvalidProfileOrder)));
+        }
+        config.reorder_profiles(order(&["Podcast", "Vlog"])).unwrap();
+        assert_eq!(config.profile_order, ["Podcast", "Vlog"]);
+    }
+
+    #[test]
+    fn set_normalization_rewrites_combos() {
+        let mut config = config(&["Vlog"]);
+        config.save_profile("Vlog", bindings(&[("LeftCtrl+1", "/sfx/a.wav"), ("RightCtrl+1", "/sfx/a.wav")])).unwrap();
+        config.set_normalization("Vlog", NormalizationPolicy::default()).unwrap();
+        assert_eq!(config.profiles["Vlog"], bindings(&[("Ctrl+1", "/sfx/a.wav")]));
+    }
+
+    #[test]
+    fn set_normalization_collision_changes_nothing() {
+        let mut config = config(&["Vlog"]);
+        let sides = NormalizationPolicy { distinguish_sides: true, ..NormalizationPolicy::default() };
+        config.profile_settings.entry("Vlog".to_string()).or_default().normalization = sides.clone();
+        config.save_profile("Vlog", bindings(&[("LeftCtrl+1", "/sfx/a.wav"), ("RightCtrl+1", "/sfx/b.wav")])).unwrap();
+        let before = config.clone();
+
+        let collision = config.set_normalization("Vlog", NormalizationPolicy::default());
+        assert!(matches!(collision, Err(ConfigError::ComboCollision { combo, .. }) if combo == "Ctrl+1"));
+        assert_eq!(config, before);
+        assert_eq!(config.settings_for("Vlog").normalization, sides);
+    }
+}
@@ -0,0 +1,572 @@
+//! WebSocket protocol: JSON envelopes (`{id, type, payload}`) plus the legacy `PREFIX:payload` strings
+//! older panels still send. Both forms decode to the same `Command` and are answered from the same `Reply`.
+
+use crate::backups::BackupInfo;
+use crate::conflicts::Conflict;
+use crate::config::{Config, ConfigError, KeyboardLayout, NormalizationPolicy, ProfileConfig, SaveConfigRequest};
+use crate::input::Trigger;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Protocol revision spoken by this build. Bump when replies or payloads change incompatibly.
+pub const PROTOCOL_VERSION: u32 = 1;
+/// Oldest client protocol revision still accepted in `HELLO`.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Every command `type` this server understands, advertised in the `HELLO` reply.
+pub const COMMANDS: &[&str] = &[
+    "AUTH",
+    "HELLO",
+    "LOAD_CONFIG",
+    "SAVE_CONFIG",
+    "DELETE_PROFILE",
+    "SWITCH_PROFILE",
+    "CREATE_PROFILE",
+    "RENAME_PROFILE",
+    "DUPLICATE_PROFILE",
+    "REORDER_PROFILES",
+    "GET_PROFILES",
+    "GET_CURRENT_PROFILE",
+    "GET_LAST_SELECTED_PROFILE",
+    "SAVE_LAST_SELECTED_PROFILE",
+    "LIST_BACKUPS",
+    "RESTORE_BACKUP",
+    "STATUS",
+    "SET_NORMALIZATION",
+    "GET_KEY_LABELS",
+    "CHECK_CONFLICTS",
+    "SET_ARMED",
+    "MIDI_LEARN",
+    "REMOTE_TRIGGER",
+];
+
+/// Optional behaviours a client can check for before relying on them.
+pub const FEATURES: &[&str] = &[
+    "json-envelope",
+    "legacy-commands",
+    "config-backups",
+    "config-hot-reload",
+    "config-events",
+    "key-sequences",
+    "gestures",
+    "keyboard-layouts",
+    "server-triggers",
+    "conflict-checks",
+    "trigger-gating",
+    "midi",
+    "remote-triggers",
+    "auth-token",
+];
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", content = "payload", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Command {
+    /// Must come first unless the token was in the URL (`?token=`); anything else closes the socket.
+    Auth { token: String },
+    Hello(Hello),
+    /// Whole config without a payload, otherwise that profile's bindings.
+    LoadConfig(Option<ProfileName>),
+    SaveConfig(SaveConfigRequest),
+    DeleteProfile { profile: String },
+    SwitchProfile { profile: String },
+    CreateProfile { profile: String },
+    RenameProfile(ProfileRename),
+    DuplicateProfile(ProfileRename),
+    ReorderProfiles { order: Vec<String> },
+    GetProfiles,
+    GetCurrentProfile,
+    GetLastSelectedProfile,
+    SaveLastSelectedProfile { profile: String },
+    ListBackups,
+    RestoreBackup { name: String },
+    Status,
+    SetNormalization(NormalizationChange),
+    /// Labels for combos the panel shows, e.g. its bindings.
+    GetKeyLabels { combos: Vec<String> },
+    /// Checks that profile, or the current one without a payload.
+    CheckConflicts(Option<ProfileName>),
+    /// Every panel hears about the change through an `ARMED` push.
+    SetArmed { armed: bool },
+    /// The next MIDI control pressed comes back as a `MIDI_LEARNED` push instead of triggering.
+    MidiLearn,
+    /// Fires a binding as if its combo had been typed, for Stream Deck buttons and scripts.
+    RemoteTrigger(TriggerRequest),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Hello {
+    pub protocol: u32,
+    pub role: ClientRole,
+    /// Free-form client name/version, only used for logging.
+    #[serde(default)]
+    pub client: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ClientRole {
+    MainPanel,
+    SettingsPanel,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProfileName {
+    pub profile: String,
+}
+
+/// Source and target names for `RENAME_PROFILE` / `DUPLICATE_PROFILE`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileRename {
+    pub from: String,
+    pub to: String,
+}
+
+/// A profile's new normalization policy, for `SET_NORMALIZATION`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NormalizationChange {
+    pub profile: String,
+    pub policy: NormalizationPolicy,
+}
+
+/// What `REMOTE_TRIGGER` fires: a combo as typed (`Ctrl+1`), or a binding of the current profile
+/// by its key or sound file name. Exactly one of the two.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TriggerRequest {
+    #[serde(default)]
+    pub combo: Option<String>,
+    #[serde(default)]
+    pub binding: Option<String>,
+}
+
+/// A JSON request. `id` is echoed back on the reply so clients can match them up.
+#[derive(Debug, Deserialize)]
+pub struct Request {
+    #[serde(default)]
+    pub id: Option<Value>,
+    #[serde(flatten)]
+    pub command: Command,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    BadRequest,
+    UnknownCommand,
+    InvalidConfig,
+    ProfileNotFound,
+    ProfileExists,
+    InvalidProfileName,
+    BackupNotFound,
+    BindingNotFound,
+    Unauthorized,
+    StorageError,
+    IncompatibleProtocol,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "payload", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Reply {
+    Authenticated,
+    #[serde(rename_all = "camelCase")]
+    Welcome {
+        server_version: &'static str,
+        protocol: u32,
+        min_protocol: u32,
+        role: ClientRole,
+        commands: &'static [&'static str],
+        features: &'static [&'static str],
+    },
+    Config(Box<Config>),
+    ProfileConfig { profile: String, config: ProfileConfig },
+    /// `conflicts` warns about what the saved bindings collide with; the save went through anyway.
+    ConfigSaved {
+        profile: String,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        conflicts: Vec<Conflict>,
+    },
+    ProfileDeleted { profile: String },
+    ProfileSwitched { profile: String, config: ProfileConfig },
+    ProfileCreated { profile: String },
+    ProfileRenamed(ProfileRename),
+    ProfileDuplicated(ProfileRename),
+    ProfilesReordered { profiles: Vec<String> },
+    Profiles { profiles: Vec<String> },
+    CurrentProfile { profile: Option<String> },
+    LastSelectedProfile { profile: Option<String> },
+    LastSelectedProfileSaved { profile: String },
+    Backups { backups: Vec<BackupInfo> },
+    BackupRestored { name: String, config: Box<Config> },
+    #[serde(rename_all = "camelCase")]
+    Status { input_tasks: usize, subscribers: usize, connections: usize, armed: bool },
+    /// The profile's bindings after migrating them to the new policy.
+    NormalizationChanged { profile: String, policy: NormalizationPolicy, config: ProfileConfig },
+    /// Each requested combo mapped to how it reads on `layout`.
+    KeyLabels { layout: KeyboardLayout, labels: BTreeMap<String, String> },
+    Conflicts { profile: String, conflicts: Vec<Conflict> },
+    Armed { armed: bool },
+    #[serde(rename_all = "camelCase")]
+    MidiLearning { timeout_ms: u64 },
+    /// `trigger` is absent when the combo isn't bound or triggers are gated.
+    Triggered {
+        combo: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        trigger: Option<Box<Trigger>>,
+    },
+    Error { code: ErrorCode, message: String },
+}
+
+#[derive(Debug, Serialize)]
+pub struct Response<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<&'a Value>,
+    #[serde(flatten)]
+    pub reply: &'a Reply,
+}
+
+impl Reply {
+    pub fn error(code: ErrorCode, message: impl Into<String>) -> Self {
+        Reply::Error { code, message: message.into() }
+    }
+
+    /// Answers a `HELLO`, or rejects a client whose protocol revision this build cannot talk to.
+    pub fn welcome(hello: &Hello) -> Self {
+        if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&hello.protocol) {
+            return Reply::error(
+                ErrorCode::IncompatibleProtocol,
+                format!(
+                    "Client speaks protocol {}, server {} supports {}..={}",
+                    hello.protocol,
+                    env!("CARGO_PKG_VERSION"),
+                    MIN_PROTOCOL_VERSION,
+                    PROTOCOL_VERSION
+                ),
+            );
+        }
+
+        Reply::Welcome {
+            server_version: env!("CARGO_PKG_VERSION"),
+            protocol: PROTOCOL_VERSION,
+            min_protocol: MIN_PROTOCOL_VERSION,
+            role: hello.role,
+            commands: COMMANDS,
+            features: FEATURES,
+        }
+    }
+
+    pub fn unauthorized() -> Self {
+        Reply::error(ErrorCode::Unauthorized, "Missing or wrong auth token")
+    }
+
+    pub fn profile_not_found(profile: &str) -> Self {
+        Reply::error(ErrorCode::ProfileNotFound, format!("Profile '{}' does not exist", profile))
+    }
+
+    /// Serializes as a JSON envelope answering request `id`.
+    pub fn to_json(&self, id: Option<&Value>) -> String {
+        serde_json::to_string(&Response { id, reply: self }).expect("Failed to serialize reply")
+    }
+
+    /// The string messages an old panel expects for this reply, in order. Some replies have none.
+    pub fn to_legacy(&self) -> Vec<String> {
+        match self {
+            Reply::Authenticated => vec!["AUTHENTICATED".to_string()],
+            Reply::Welcome { server_version, protocol, .. } => {
+                vec![format!("WELCOME:{}:{}", server_version, protocol)]
+            }
+            Reply::Config(config) => vec![format!("CONFIG:{}", to_json(config))],
+            Reply::ProfileConfig { config, .. } => vec![format!("CONFIG:{}", to_json(config))],
+            Reply::ConfigSaved { profile, conflicts } => {
+                let mut messages = vec!["CONFIG_SAVED".to_string()];
+                if !conflicts.is_empty() {
+                    let conflicts = Reply::Conflicts { profile: profile.clone(), conflicts: conflicts.clone() };
+                    messages.extend(conflicts.to_legacy());
+                }
+                messages
+            }
+            Reply::ProfileDeleted { profile } => vec![format!("PROFILE_DELETED:{}", profile)],
+            Reply::ProfileSwitched { profile, config } => vec![
+                format!("PROFILE_SWITCHED:{}", profile),
+                format!("CONFIG:{}", to_json(config)),
+            ],
+            Reply::ProfileCreated { profile } => vec![format!("PROFILE_CREATED:{}", profile)],
+            Reply::ProfileRenamed(rename) => vec![format!("PROFILE_RENAMED:{}", to_json(rename))],
+            Reply::ProfileDuplicated(rename) => vec![format!("PROFILE_DUPLICATED:{}", to_json(rename))],
+            Reply::ProfilesReordered { profiles } | Reply::Profiles { profiles } => {
+                vec![format!("PROFILES:{}", to_json(profiles))]
+            }
+            Reply::CurrentProfile { profile } => {
+                vec![format!("CURRENT_PROFILE:{}", profile.as_deref().unwrap_or("None"))]
+            }
+            Reply::LastSelectedProfile { profile } => {
+                vec![format!("LAST_SELECTED_PROFILE:{}", profile.as_deref().unwrap_or("None"))]
+            }
+            Reply::LastSelectedProfileSaved { .. } => Vec::new(),
+            Reply::Backups { backups } => vec![format!("BACKUPS:{}", to_json(backups))],
+            Reply::BackupRestored { name, config } => vec![
+                format!("BACKUP_RESTORED:{}", name),
+                format!("CONFIG:{}", to_json(config)),
+            ],
+            Reply::Status { .. } => vec![format!("STATUS:{}", to_json(self))],
+            Reply::NormalizationChanged { .. } => vec![format!("NORMALIZATION_CHANGED:{}", to_json(self))],
+            Reply::KeyLabels { labels, .. } => vec![format!("KEY_LABELS:{}", to_json(labels))],
+            Reply::Conflicts { .. } => vec![format!("CONFLICTS:{}", to_json(self))],
+            // The `ARMED:` push reaches this panel too
+            Reply::Armed { .. } => Vec::new(),
+            Reply::MidiLearning { .. } => vec!["MIDI_LEARNING".to_string()],
+            Reply::Triggered { .. } => vec![format!("TRIGGERED:{}", to_json(self))],
+            Reply::Error { message, .. } => vec![format!("ERROR:{}", message)],
+        }
+    }
+}
+
+impl From<ConfigError> for Reply {
+    fn from(e: ConfigError) -> Self {
+        let code = match e {
+            ConfigError::Io(_) | ConfigError::NewerSchema { .. } => ErrorCode::StorageError,
+            ConfigError::Parse(_) => ErrorCode::BadRequest,
+            ConfigError::UnknownBackup(_) => ErrorCode::BackupNotFound,
+            ConfigError::ProfileNotFound(_) => ErrorCode::ProfileNotFound,
+            ConfigError::ProfileExists(_) => ErrorCode::ProfileExists,
+            ConfigError::EmptyProfileName | ConfigError::InvalidProfileName(_) => ErrorCode::InvalidProfileName,
+            _ => ErrorCode::InvalidConfig,
+        };
+        Reply::error(code, e.to_string())
+    }
+}
+
+/// Decodes one text frame. `Ok((id, command))` for a valid request, `Err((id, reply))` with the error to send.
+pub fn parse_message(text: &str) -> Result<(Option<Value>, Command), (Option<Value>, Reply)> {
+    if is_json(text) {
+        parse_json(text)
+    } else {
+        parse_legacy(text).map(|command| (None, command)).map_err(|reply| (None, reply))
+    }
+}
+
+/// JSON envelopes always start with `{`; no legacy command does.
+pub fn is_json(text: &str) -> bool {
+    text.trim_start().starts_with('{')
+}
+
+/// Builds the command another transport (HTTP) asks for, decoded and validated exactly like a
+/// JSON envelope of that `type` and `payload`.
+pub fn build_command(kind: &str, payload: Option<Value>) -> Result<Command, Reply> {
+    let mut raw = serde_json::Map::new();
+    raw.insert("type".to_string(), Value::from(kind));
+    if let Some(payload) = payload {
+        raw.insert("payload".to_string(), payload);
+    }
+    parse_envelope(Value::Object(raw)).map(|(_, command)| command).map_err(|(_, reply)| reply)
+}
+
+fn parse_json(text: &str) -> Result<(Option<Value>, Command), (Option<Value>, Reply)> {
+    let raw: Value = serde_json::from_str(text)
+        .map_err(|e| (None, Reply::error(ErrorCode::BadRequest, format!("Invalid JSON: {}", e))))?;
+    parse_envelope(raw)
+}
+
+fn parse_envelope(mut raw: Value) -> Result<(Option<Value>, Command), (Option<Value>, Reply)> {
+    let id = raw.get("id").cloned();
+
+    // `"payload": {}` / `null` on a command that takes none is the same as leaving it out
+    if let Some(object) = raw.as_object_mut() {
+        let empty = object
+            .get("payload")
+            .is_some_and(|p| p.is_null() || p.as_object().is_some_and(|o| o.is_empty()));
+        if empty {
+            object.remove("payload");
+        }
+    }
+
+    // Told apart before decoding, so a bad payload of a known command isn't mistaken for an unknown one
+    match raw.get("type").and_then(Value::as_str) {
+        Some(kind) if COMMANDS.contains(&kind) => {}
+        Some(kind) => {
+            return Err((id, Reply::error(ErrorCode::UnknownCommand, format!("Unknown command type: {}", kind))))
+        }
+        None => return Err((id, Reply::error(ErrorCode::BadRequest, "Missing command type"))),
+    }
+
+    let request: Request = serde_json::from_value(raw)
+        .map_err(|e| (id.clone(), Reply::error(ErrorCode::BadRequest, format!("Invalid request: {}", e))))?;
+
+    match &request.command {
+        Command::SaveConfig(save) => save.validate().map_err(|e| (id.clone(), Reply::from(e)))?,
+        Command::RemoteTrigger(trigger) => trigger.validate().map_err(|reply| (id.clone(), reply))?,
+        _ => {}
+    }
+
+    Ok((request.id, request.command))
+}
+
+/// Compatibility adapter for the `PREFIX:payload` commands sent by panels that predate the JSON envelope.
+pub fn parse_legacy(text: &str) -> Result<Command, Reply> {
+    let command = if let Some(token) = text.strip_prefix("AUTH:") {
+        Command::Auth { token: token.to_string() }
+    } else if let Some(payload) = text.strip_prefix("HELLO:") {
+        Command::Hello(parse_legacy_payload("HELLO", payload)?)
+    } else if let Some(payload) = text.strip_prefix("SAVE_CONFIG:") {
+        Command::SaveConfig(SaveConfigRequest::parse(payload)?)
+    } else if let Some(name) = text.strip_prefix("DELETE_PROFILE:") {
+        Command::DeleteProfile { profile: name.to_string() }
+    } else if let Some(name) = text.strip_prefix("CREATE_PROFILE:") {
+        Command::CreateProfile { profile: name.to_string() }
+    } else if let Some(payload) = text.strip_prefix("RENAME_PROFILE:") {
+        Command::RenameProfile(parse_legacy_payload("RENAME_PROFILE", payload)?)
+    } else if let Some(payload) = text.strip_prefix("DUPLICATE_PROFILE:") {
+        Command::DuplicateProfile(parse_legacy_payload("DUPLICATE_PROFILE", payload)?)
+    } else if let Some(payload) = text.strip_prefix("REORDER_PROFILES:") {
+        Command::ReorderProfiles { order: parse_legacy_payload("REORDER_PROFILES", payload)? }
+    } else if text == "LOAD_CONFIG" {
+        Command::LoadConfig(None)
+    } else if let Some(name) = text.strip_prefix("LOAD_CONFIG:") {
+        Command::LoadConfig(Some(ProfileName { profile: name.to_string() }))
+    } else if text == "GET_CURRENT_PROFILE" {
+        Command::GetCurrentProfile
+    } else if let Some(name) = text.strip_prefix("SAVE_LAST_SELECTED_PROFILE:") {
+        Command::SaveLastSelectedProfile { profile: name.to_string() }
+    } else if text == "GET_LAST_SELECTED_PROFILE" {
+        Command::GetLastSelectedProfile
+    } else if text == "GET_PROFILES" {
+        Command::GetProfiles
+    } else if let Some(name) = text.strip_prefix("SWITCH_PROFILE:") {
+        Command::SwitchProfile { profile: name.to_string() }
+    } else if text == "LIST_BACKUPS" {
+        Command::ListBackups
+    } else if text == "STATUS" {
+        Command::Status
+    } else if let Some(payload) = text.strip_prefix("SET_NORMALIZATION:") {
+        Command::SetNormalization(parse_legacy_payload("SET_NORMALIZATION", payload)?)
+    } else if let Some(armed) = text.strip_prefix("SET_ARMED:") {
+        Command::SetArmed { armed: parse_legacy_payload("SET_ARMED", armed)? }
+    } else if text == "MIDI_LEARN" {
+        Command::MidiLearn
+    } else if let Some(payload) = text.strip_prefix("REMOTE_TRIGGER:") {
+        let trigger: TriggerRequest = parse_legacy_payload("REMOTE_TRIGGER", payload)?;
+        trigger.validate()?;
+        Command::RemoteTrigger(trigger)
+    } else if text == "CHECK_CONFLICTS" {
+        Command::CheckConflicts(None)
+    } else if let Some(name) = text.strip_prefix("CHECK_CONFLICTS:") {
+        Command::CheckConflicts(Some(ProfileName { profile: name.to_string() }))
+    } else if let Some(payload) = text.strip_prefix("GET_KEY_LABELS:") {
+        Command::GetKeyLabels { combos: parse_legacy_payload("GET_KEY_LABELS", payload)? }
+    } else if let Some(name) = text.strip_prefix("RESTORE_BACKUP:") {
+        Command::RestoreBackup { name: name.to_string() }
+    } else {
+        let name = text.split(':').next().unwrap_or(text);
+        return Err(Reply::error(ErrorCode::UnknownCommand, format!("Unknown command: {}", name)));
+    };
+
+    Ok(command)
+}
+
+impl TriggerRequest {
+    pub fn validate(&self) -> Result<(), Reply> {
+        match (&self.combo, &self.binding) {
+            (Some(_), None) | (None, Some(_)) => Ok(()),
+            _ => Err(Reply::error(ErrorCode::BadRequest, "Give either a combo or a binding to trigger")),
+        }
+    }
+}
+
+fn parse_legacy_payload<T: serde::de::DeserializeOwned>(command: &str, payload: &str) -> Result<T, Reply> {
+    serde_json::from_str(payload)
+        .map_err(|e| Reply::error(ErrorCode::BadRequest, format!("Invalid {} payload: {}", command, e)))
+}
+
+fn to_json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).expect("Failed to serialize reply")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code(reply: &Reply) -> Option<ErrorCode> {
+        match reply {
+            Reply::Error { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+
+    fn json_error(text: &str) -> (Option<Value>, Option<ErrorCode>) {
+        let (id, reply) = parse_message(text).expect_err("an error reply");
+        (id, code(&reply))
+    }
+
+    #[test]
+    fn every_advertised_command_is_known() {
+        for kind in COMMANDS {
+            let unknown = build_command(kind, None).err().and_then(|reply| code(&reply)) == Some(ErrorCode::UnknownCommand);
+            assert!(!unknown, "{}", kind);
+        }
+    }
+
+    #[test]
+    fn profile_names_may_contain_a_command_prefix() {
+        let Ok((None, Command::LoadConfig(Some(name)))) = parse_message("LOAD_CONFIG:LOAD_CONFIG:Vlog") else {
+            panic!("not a LOAD_CONFIG");
+        };
+        assert_eq!(name.profile, "LOAD_CONFIG:Vlog");
+
+        let json = r#"{"type": "SWITCH_PROFILE", "payload": {"profile": "LOAD_CONFIG:Vlog"}}"#;
+        let Ok((_, Command::SwitchProfile { profile })) = parse_message(json) else { panic!("not a SWITCH_PROFILE") };
+        assert_eq!(profile, "LOAD_CONFIG:Vlog");
+    }
+
+    #[test]
+    fn id_is_echoed_on_the_reply() {
+        let Ok((id, Command::GetProfiles)) = parse_message(r#"{"id": 7, "type": "GET_PROFILES"}"#) else {
+            panic!("not a GET_PROFILES");
+        };
+        let reply: Value = serde_json::from_str(&Reply::Profiles { profiles: Vec::new() }.to_json(id.as_ref())).unwrap();
+        assert_eq!(reply["id"], 7);
+        assert_eq!(reply["type"], "PROFILES");
+    }
+
+    #[test]
+    fn id_is_echoed_on_errors_too() {
+        assert_eq!(json_error(r#"{"id": "a", "type": "LAUNCH"}"#), (Some(Value::from("a")), Some(ErrorCode::UnknownCommand)));
+        assert_eq!(
+            json_error(r#"{"id": "b", "type": "SWITCH_PROFILE", "payload": {"name": "Vlog"}}"#),
+            (Some(Value::from("b")), Some(ErrorCode::BadRequest))
+        );
+    }
+
+    #[test]
+    fn unknown_commands_and_bad_payloads_have_their_own_codes() {
+        assert_eq!(json_error(r#"{"type": "LAUNCH"}"#).1, Some(ErrorCode::UnknownCommand));
+        assert_eq!(json_error(r#"{"type": "SET_ARMED", "payload": {"armed": "yes"}}"#).1, Some(ErrorCode::BadRequest));
+        assert_eq!(json_error(r#"{"type": "SET_ARMED"}"#).1, Some(ErrorCode::BadRequest));
+        assert_eq!(json_error(r#"{"payload": {}}"#).1, Some(ErrorCode::BadRequest));
+        assert_eq!(json_error(r#"{"type": 3}"#).1, Some(ErrorCode::BadRequest));
+        assert_eq!(json_error("{not json").1, Some(ErrorCode::BadRequest));
+        assert_eq!(json_error(r#"{"type": "REMOTE_TRIGGER", "payload": {}}"#).1, Some(ErrorCode::BadRequest));
+    }
+
+    #[test]
+    fn empty_payload_is_the_same_as_none() {
+        assert!(matches!(parse_message(r#"{"type": "LOAD_CONFIG", "payload": {}}"#), Ok((_, Command::LoadConfig(None)))));
+        assert!(matches!(parse_message(r#"{"type": "GET_PROFILES", "payload": null}"#), Ok((_, Command::GetProfiles))));
+    }
+
+    #[test]
+    fn legacy_commands_decode_like_their_envelopes() {
+        assert!(matches!(parse_legacy("LOAD_CONFIG"), Ok(Command::LoadConfig(None))));
+        assert!(matches!(parse_legacy("SET_ARMED:false"), Ok(Command::SetArmed { armed: false })));
+        let Ok(Command::RenameProfile(rename)) = parse_legacy(r#"RENAME_PROFILE:{"from": "A", "to": "B"}"#) else {
+            panic!("not a RENAME_PROFILE");
+        };
+        assert_eq!((rename.from.as_str(), rename.to.as_str()), ("A", "B"));
+    }
+
+    #[test]
+    fn legacy_errors_have_their_own_codes() {
+        assert_eq!(parse_legacy("LAUNCH:now").err().and_then(|reply| code(&reply)), Some(ErrorCode::UnknownCommand));
+        assert_eq!(parse_legacy("SET_ARMED:yes").err().and_then(|reply| code(&reply)), Some(ErrorCode::BadRequest));
+        assert_eq!(parse_legacy("REMOTE_TRIGGER:{}").err().and_then(|reply| code(&reply)), Some(ErrorCode::BadRequest));
+    }
+}
@@ -0,0 +1,164 @@
+//! Physical key ids on Windows, where both input backends name keys after the virtual key the
+//! active layout produces: an AZERTY `A` arrives as `A` although it sits where US-QWERTY has `Q`.
+//! Going through the key's scancode gives back the US-QWERTY name other platforms already report,
+//! so a binding means the same physical key whatever layout recorded it.
+
+/// The layout-dependent virtual keys, by the name the backends give them on a US layout. Letters
+/// and digits are their own ASCII code.
+const OEM_KEYS: &[(&str, u32)] = &[
+    ("Semicolon", 0xBA),
+    ("Equal", 0xBB),
+    ("Comma", 0xBC),
+    ("Minus", 0xBD),
+    ("Dot", 0xBE),
+    ("Slash", 0xBF),
+    ("Grave", 0xC0),
+    ("LeftBracket", 0xDB),
+    ("BackSlash", 0xDC),
+    ("RightBracket", 0xDD),
+    ("Apostrophe", 0xDE),
+    ("IntlBackslash", 0xE2),
+];
+
+/// Set 1 scancodes of the keys a layout can move, named after their US-QWERTY legend.
+const SCANCODES: &[(u32, &str)] = &[
+    (0x02, "1"),
+    (0x03, "2"),
+    (0x04, "3"),
+    (0x05, "4"),
+    (0x06, "5"),
+    (0x07, "6"),
+    (0x08, "7"),
+    (0x09, "8"),
+    (0x0A, "9"),
+    (0x0B, "0"),
+    (0x0C, "Minus"),
+    (0x0D, "Equal"),
+    (0x10, "Q"),
+    (0x11, "W"),
+    (0x12, "E"),
+    (0x13, "R"),
+    (0x14, "T"),
+    (0x15, "Y"),
+    (0x16, "U"),
+    (0x17, "I"),
+    (0x18, "O"),
+    (0x19, "P"),
+    (0x1A, "LeftBracket"),
+    (0x1B, "RightBracket"),
+    (0x1E, "A"),
+    (0x1F, "S"),
+    (0x20, "D"),
+    (0x21, "F"),
+    (0x22, "G"),
+    (0x23, "H"),
+    (0x24, "J"),
+    (0x25, "K"),
+    (0x26, "L"),
+    (0x27, "Semicolon"),
+    (0x28, "Apostrophe"),
+    (0x29, "Grave"),
+    (0x2B, "BackSlash"),
+    (0x2C, "Z"),
+    (0x2D, "X"),
+    (0x2E, "C"),
+    (0x2F, "V"),
+    (0x30, "B"),
+    (0x31, "N"),
+    (0x32, "M"),
+    (0x33, "Comma"),
+    (0x34, "Dot"),
+    (0x35, "Slash"),
+    (0x56, "IntlBackslash"),
+];
+
+fn virtual_key(name: &str) -> Option<u32> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_uppercase() || c.is_ascii_digit() => Some(c as u32),
+        _ => OEM_KEYS.iter().find(|(oem, _)| *oem == name).map(|(_, code)| *code),
+    }
+}
+
+/// The physical key behind a key name a backend derived from a virtual key. Keys no layout moves,
+/// and every key off Windows, come back unchanged.
+pub fn physical_key(name: String) -> String {
+    physical_key_with(name, scancode)
+}
+
+fn physical_key_with(name: String, scancode: impl Fn(u32) -> Option<u32>) -> String {
+    virtual_key(&name)
+        .and_then(scancode)
+        .and_then(|code| SCANCODES.iter().find(|(scancode, _)| *scancode == code))
+        .map_or(name, |(_, physical)| physical.to_string())
+}
+
+#[cfg(windows)]
+fn scancode(virtual_key: u32) -> Option<u32> {
+    use std::ptr;
+    use winapi::um::winuser::{
+        GetForegroundWindow, GetKeyboardLayout, GetWindowThreadProcessId, MapVirtualKeyExW, MAPVK_VK_TO_VSC,
+    };
+
+    // The backends' threads keep the layout they started with; the key was typed in the
+    // foreground window's
+    let code = unsafe {
+        let thread = GetWindowThreadProcessId(GetForegroundWindow(), ptr::null_mut());
+        MapVirtualKeyExW(virtual_key, MAPVK_VK_TO_VSC, GetKeyboardLayout(thread))
+    };
+    (code != 0).then_some(code)
+}
+
+#[cfg(not(windows))]
+fn scancode(_virtual_key: u32) -> Option<u32> {
+    // X11 and macOS already report the physical key
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Where a few virtual keys sit on a layout, as `MapVirtualKeyExW` would answer.
+    fn layout(virtual_keys: &'static [(u32, u32)]) -> impl Fn(u32) -> Option<u32> {
+        move |key| {
+            virtual_keys
+                .iter()
+                .find(|(virtual_key, _)| *virtual_key == key)
+                .map(|(_, scancode)| *scancode)
+                .or_else(|| SCANCODES.iter().find(|(_, name)| virtual_key(name) == Some(key)).map(|(code, _)| *code))
+        }
+    }
+
+    fn physical(names: &[&str], scancode: impl Fn(u32) -> Option<u32>) -> Vec<String> {
+        names.iter().map(|name| physical_key_with(name.to_string(), &scancode)).collect()
+    }
+
+    #[test]
+    fn qwerty_keys_keep_their_names() {
+        assert_eq!(
+            physical(&["A", "Q", "1", "Semicolon", "F5", "LeftCtrl"], layout(&[])),
+            ["A", "Q", "1", "Semicolon", "F5", "LeftCtrl"]
+        );
+    }
+
+    #[test]
+    fn azerty_letters_and_punctuation_become_physical_keys() {
+        // A and Q, Z and W swap; M sits on Semicolon and VK_OEM_COMMA on M
+        let azerty = layout(&[(0x41, 0x10), (0x51, 0x1E), (0x5A, 0x11), (0x57, 0x2C), (0x4D, 0x27), (0xBC, 0x32)]);
+        assert_eq!(physical(&["A", "Q", "Z", "W", "M", "Comma", "1"], azerty), ["Q", "A", "W", "Z", "Semicolon", "M", "1"]);
+    }
+
+    #[test]
+    fn qwertz_letters_and_punctuation_become_physical_keys() {
+        // Y and Z swap; VK_OEM_3 (Ö) sits on Semicolon and VK_OEM_MINUS on Slash
+        let qwertz = layout(&[(0x59, 0x2C), (0x5A, 0x15), (0xC0, 0x27), (0xBD, 0x35)]);
+        assert_eq!(physical(&["Y", "Z", "Grave", "Minus"], qwertz), ["Z", "Y", "Semicolon", "Slash"]);
+    }
+
+    #[test]
+    fn unmapped_keys_are_left_alone() {
+        assert_eq!(physical(&["A", "Dot"], |_| None), ["A", "Dot"]);
+        assert_eq!(physical(&["A"], |_| Some(0x7F)), ["A"]);
+    }
+}
@@ -1,14 +1,46 @@
 use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::handshake::server::{Callback, ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::header::ORIGIN;
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
 use tokio_tungstenite::tungstenite::Message;
 use futures_util::{SinkExt, StreamExt};
-use device_query::{DeviceQuery, DeviceState, Keycode};
-use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::{Mutex, broadcast};
-use std::env;
-use std::fs;
-use std::path::PathBuf;
-use serde_json::Value;
+
+mod auth;
+mod backups;
+mod cli;
+mod commands;
+mod config;
+mod conflicts;
+mod discovery;
+mod events;
+mod foreground;
+mod http;
+mod input;
+mod keys;
+mod layouts;
+mod migrations;
+mod osc;
+mod paths;
+mod premiere;
+mod profiles;
+mod protocol;
+mod scancodes;
+mod state;
+mod watcher;
+
+use config::load_config;
+use commands::Session;
+use events::ServerEvent;
+use input::InputHub;
+use state::AppState;
+use protocol::{Command, ErrorCode, Reply};
 
 type WebSocketTx = futures_util::stream::SplitSink<
     tokio_tungstenite::WebSocketStream<tokio::net::TcpStream>,
@@ -20,485 +52,386 @@ type WebSocketRx = futures_util::stream::SplitStream<
 
 #[tokio::main]
 async fn main() {
-    let addr = "127.0.0.1:7878";
-    let listener = TcpListener::bind(&addr).await.expect("Can't listen");
-    println!("Listening on: {}", addr);
-
-    let config = Arc::new(Mutex::new(load_config()));
-    let (tx, _rx) = broadcast::channel(100);
-
-    while let Ok((stream, _)) = listener.accept().await {
-        let config_clone = Arc::clone(&config);
-        let tx_clone = tx.clone();
-        tokio::spawn(accept_connection(stream, config_clone, tx_clone));
+    let options = cli::CliOptions::parse();
+    match paths::init_config_path(&options) {
+        Ok(config_path) => println!("Using config file: {}", config_path.display()),
+        Err(e) => {
+            eprintln!("Failed to prepare config directory: {}", e);
+            std::process::exit(1);
+        }
     }
-}
-
-async fn accept_connection(
-    stream: TcpStream,
-    config_clone: Arc<Mutex<Value>>,
-    tx: broadcast::Sender<String>,
-) {
-    let addr = stream.peer_addr().expect("Connected streams should have a peer address");
-    println!("New WebSocket connection: {}", addr);
-
-    let ws_stream = tokio_tungstenite::accept_async(stream)
-        .await
-        .expect("Error during the websocket handshake occurred");
 
-    let (write, read) = ws_stream.split();
-    let write = Arc::new(Mutex::new(write));
-    let device_state = Arc::new(DeviceState::new());
-    let last_keys = Arc::new(Mutex::new(HashSet::new()));
+    if options.rotate_token {
+        match auth::rotate_token() {
+            Ok(_) => println!("New auth token written to {}", auth::token_path().display()),
+            Err(e) => {
+                eprintln!("Failed to write auth token {}: {}", auth::token_path().display(), e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    let auth_token = match auth::load_or_create_token() {
+        Ok(token) => Arc::from(token),
+        Err(e) => {
+            eprintln!("Failed to prepare auth token {}: {}", auth::token_path().display(), e);
+            std::process::exit(1);
+        }
+    };
 
-    let write_clone = Arc::clone(&write);
+    let config = match load_config() {
+        Ok(config) => Arc::new(Mutex::new(config)),
+        Err(e) => {
+            eprintln!("Failed to load config: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let server_settings = config.lock().await.server.clone();
+    let (tx, _rx) = broadcast::channel(100);
+    let input_settings = config.lock().await.input.clone();
+    let osc_settings = config.lock().await.osc.clone();
+    let http_settings = config.lock().await.http.clone();
+    let input_sources = match input::source_for(&input_settings, options.input_script.as_deref())
+        .and_then(|keys| Ok((keys, input::midi_source_for(&input_settings.midi, options.midi_file.as_deref())?)))
+    {
+        Ok((keys, midi)) => std::iter::once(keys).chain(midi).collect(),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
 
-    // Clone `config_clone` when passing to functions
-    tokio::spawn(handle_incoming_messages(
-        read,
-        config_clone.clone(),
-        write_clone.clone(),
-        tx.clone(),
+    tokio::spawn(watcher::watch_config(Arc::clone(&config), tx.clone()));
+
+    let state = AppState {
+        config,
+        events: tx,
+        input: InputHub::start(
+            input_sources,
+            &input_settings,
+            foreground::provider_for(options.foreground_app.as_deref()),
+        ),
+        connections: Arc::new(AtomicUsize::new(0)),
+        auth_token,
+    };
+    tokio::spawn(input::follow_config(
+        Arc::clone(&state.input),
+        Arc::clone(&state.config),
+        state.events.subscribe(),
     ));
+    if osc_settings.enabled {
+        tokio::spawn(osc::listen(osc_settings, state.clone()));
+    }
+    let http_addr = if http_settings.enabled {
+        http::bind(http_settings, server_settings.port_fallbacks, state.clone()).await.map(|(addr, server)| {
+            tokio::spawn(server);
+            addr
+        })
+    } else {
+        None
+    };
 
-    tokio::spawn(handle_key_events(
-        device_state,
-        last_keys,
-        write_clone,
-        tx,
-        config_clone.clone(),
-    ));
+    let host = options.host.as_deref().unwrap_or(&server_settings.host);
+    let port = options.port.unwrap_or(server_settings.port);
+    let listener = match listen(host, port, server_settings.port_fallbacks).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let addr = listener.local_addr().expect("Listener has no address");
+    println!("Listening on: {}", addr);
+    match discovery::write(addr, http_addr) {
+        Ok(path) => println!("Wrote server address to {}", path.display()),
+        Err(e) => eprintln!("Failed to write {}: {}", paths::discovery_path().display(), e),
+    }
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    tokio::spawn(accept_connection(stream, state.clone()));
+                }
+                Err(e) => {
+                    eprintln!("Failed to accept connection: {}", e);
+                    break;
+                }
+            },
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+    discovery::remove();
 }
 
+/// Binds `host:port`, moving on to the next of up to `fallbacks` ports while the one tried is
+/// already taken by another program.
+async fn listen(host: &str, port: u16, fallbacks: u16) -> Result<TcpListener, String> {
+    let last = port.saturating_add(fallbacks);
+    let mut candidate = port;
+    loop {
+        match TcpListener::bind((host, candidate)).await {
+            Ok(listener) => return Ok(listener),
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse && candidate < last => {
+                eprintln!("Port {} is in use, trying {}", candidate, candidate + 1);
+                candidate += 1;
+            }
+            Err(e) => return Err(format!("Can't listen on {}:{}: {}", host, candidate, e)),
+        }
+    }
+}
 
-async fn handle_delete_profile(
-    profile_name: String,
-    config: Arc<Mutex<Value>>,
-    write: Arc<Mutex<WebSocketTx>>
-) {
-    // Lock the config to modify it
-    let mut config_guard = config.lock().await;
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+/// How long a client that didn't put the token in the URL has to send `AUTH`.
+const AUTH_TIMEOUT: Duration = Duration::from_secs(5);
 
-    // Check if the profile exists in the configuration
-    if config_guard["profiles"].get(&profile_name).is_some() {
-        // Remove the profile from the configuration
-        config_guard["profiles"].as_object_mut().unwrap().remove(&profile_name);
+async fn accept_connection(stream: TcpStream, state: AppState) {
+    let addr = match stream.peer_addr() {
+        Ok(addr) => addr,
+        Err(e) => {
+            eprintln!("Dropping connection without a peer address: {}", e);
+            return;
+        }
+    };
+    println!("New WebSocket connection: {}", addr);
 
-        // If the deleted profile was the current profile, reset currentProfile
-        if config_guard["currentProfile"].as_str() == Some(&profile_name) {
-            config_guard["currentProfile"] = Value::Null;
+    let allowed_origins = state.config.lock().await.server.allowed_origins.clone();
+    let mut url_token = None;
+    let check = HandshakeCheck {
+        peer: addr,
+        allowed_origins: &allowed_origins,
+        auth_token: &state.auth_token,
+        url_token: &mut url_token,
+    };
+    let ws_stream = match tokio_tungstenite::accept_hdr_async(stream, check).await {
+        Ok(ws_stream) => ws_stream,
+        Err(e) => {
+            eprintln!("WebSocket handshake with {} failed: {}", addr, e);
+            return;
         }
+    };
 
-        // Save the updated configuration
-        save_config(&config_guard);
+    let (write, mut read) = ws_stream.split();
+    let write = Arc::new(Mutex::new(write));
+    // Flipped once the client sends JSON, so pushed messages arrive in the dialect it speaks
+    let speaks_json = Arc::new(AtomicBool::new(false));
 
-        // Send a message back to the client indicating the profile was deleted
+    if url_token.is_none() && !authenticate(&mut read, &write, &speaks_json, &state.auth_token).await {
+        eprintln!("Closed unauthenticated WebSocket connection: {}", addr);
         let mut write_guard = write.lock().await;
         let _ = write_guard
-            .send(Message::Text(format!("PROFILE_DELETED:{}", profile_name)))
-            .await;
-    } else {
-        // If the profile does not exist, send an error message back to the client
-        let mut write_guard = write.lock().await;
-        let _ = write_guard
-            .send(Message::Text(format!("ERROR:Profile '{}' does not exist", profile_name)))
+            .send(Message::Close(Some(CloseFrame { code: CloseCode::Policy, reason: "Unauthorized".into() })))
             .await;
+        return;
     }
-}
+    let connection_id = NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
 
+    state.connections.fetch_add(1, Ordering::Relaxed);
 
+    let events_task = tokio::spawn(forward_events(
+        state.events.subscribe(),
+        connection_id,
+        Arc::clone(&speaks_json),
+        Arc::clone(&write),
+    ));
+    let combos_task = tokio::spawn(forward_combos(
+        state.input.subscribe(),
+        state.input.armed(),
+        Arc::clone(&speaks_json),
+        Arc::clone(&write),
+    ));
 
-async fn handle_incoming_messages(
-    mut read: WebSocketRx,
-    config: Arc<Mutex<Value>>,
-    write: Arc<Mutex<WebSocketTx>>,
-    tx: broadcast::Sender<String>,
-) {
-    while let Some(message) = read.next().await {
-        let message = message.expect("Failed to read message");
+    let mut session = Session::new(connection_id);
+    handle_incoming_messages(read, state.clone(), write, &mut session, speaks_json).await;
 
-        if let Message::Text(text) = message {
-            if text.starts_with("SAVE_CONFIG:") {
-                let config_str = text.replace("SAVE_CONFIG:", "");
-                let parsed_message: serde_json::Value =
-                    serde_json::from_str(&config_str).expect("Failed to parse config");
+    // The socket is gone: stop delivering to it and release its subscriptions
+    events_task.abort();
+    combos_task.abort();
+    state.connections.fetch_sub(1, Ordering::Relaxed);
+    match (session.role, session.protocol) {
+        (Some(role), Some(protocol)) => {
+            println!("WebSocket connection closed: {} ({:?} on protocol {})", addr, role, protocol)
+        }
+        _ => println!("WebSocket connection closed: {} (no HELLO)", addr),
+    }
+}
 
-                let profile_name = parsed_message["profile"]
-                    .as_str()
-                    .expect("Profile name is missing")
-                    .to_string();
-                let new_keybindings = &parsed_message["config"];
+/// Refuses the upgrade from an origin that isn't allowed or with a wrong `?token=` in the URL.
+/// Leaves `Some(true)` in `url_token` when the URL carried the right token and `None` when it had none.
+struct HandshakeCheck<'a> {
+    peer: SocketAddr,
+    allowed_origins: &'a [String],
+    auth_token: &'a str,
+    url_token: &'a mut Option<bool>,
+}
 
-                let mut config_guard = config.lock().await;
+impl Callback for HandshakeCheck<'_> {
+    fn on_request(self, request: &Request, response: Response) -> Result<Response, ErrorResponse> {
+        let origin = request.headers().get(ORIGIN).map(|origin| origin.to_str().unwrap_or("<not ASCII>"));
+        if !auth::origin_allowed(origin, self.allowed_origins) {
+            eprintln!("Refused WebSocket connection from {}: origin {} is not allowed", self.peer, origin.unwrap_or_default());
+            return Err(refusal(StatusCode::FORBIDDEN, "Origin not allowed"));
+        }
 
-                if !config_guard["profiles"].is_object() {
-                    config_guard["profiles"] = serde_json::json!({});
-                }
+        let valid = auth::query_token(request.uri().query()).map(|token| auth::is_valid(self.auth_token, token));
+        *self.url_token = valid;
+        if valid == Some(false) {
+            return Err(refusal(StatusCode::UNAUTHORIZED, "Wrong auth token"));
+        }
+        Ok(response)
+    }
+}
 
-                config_guard["profiles"][profile_name.clone()] = new_keybindings.clone();
-                config_guard["currentProfile"] = serde_json::Value::String(profile_name.clone());
-                config_guard["lastSelectedProfile"] = serde_json::Value::String(profile_name.clone());
+fn refusal(status: StatusCode, reason: &str) -> ErrorResponse {
+    let mut refusal = ErrorResponse::new(Some(reason.to_string()));
+    *refusal.status_mut() = status;
+    refusal
+}
 
-                save_config(&config_guard);
+/// Waits for the client's first message, which must be `AUTH` with `auth_token`, and answers it.
+async fn authenticate(
+    read: &mut WebSocketRx,
+    write: &Arc<Mutex<WebSocketTx>>,
+    speaks_json: &AtomicBool,
+    auth_token: &str,
+) -> bool {
+    let text = loop {
+        match tokio::time::timeout(AUTH_TIMEOUT, read.next()).await {
+            Ok(Some(Ok(Message::Text(text)))) => break text,
+            // Pings and the like don't count as the first message
+            Ok(Some(Ok(Message::Ping(_) | Message::Pong(_)))) => continue,
+            _ => return false,
+        }
+    };
 
-                // Send a confirmation message back to the client
-                let mut write_guard = write.lock().await;
-                let _ = write_guard
-                    .send(Message::Text("CONFIG_SAVED".to_string()))
-                    .await;
+    let is_json = protocol::is_json(&text);
+    speaks_json.store(is_json, Ordering::Relaxed);
+    let (id, reply) = match protocol::parse_message(&text) {
+        Ok((id, Command::Auth { token })) if auth::is_valid(auth_token, &token) => (id, Reply::Authenticated),
+        Ok((id, _)) | Err((id, _)) => (id, Reply::unauthorized()),
+    };
+    let authenticated = matches!(reply, Reply::Authenticated);
 
-                println!("Config saved for profile: {}", profile_name);
-            }
-            // Handle profile deletion
-            else if text.starts_with("DELETE_PROFILE:") {
-                // Extract profile name from the message
-                let profile_name = text.replace("DELETE_PROFILE:", "");
-                
-                // Call the `handle_delete_profile` function to delete the profile
-                handle_delete_profile(profile_name, Arc::clone(&config), Arc::clone(&write)).await;
-            }
-            else if text == "LOAD_CONFIG" {
-                let config_guard = config.lock().await;
-                let config_str = serde_json::to_string(&*config_guard).expect("Failed to serialize config");
-                let mut write_guard = write.lock().await;
-                let _ = write_guard
-                    .send(Message::Text(format!("CONFIG:{}", config_str)))
-                    .await;
-                println!("Sent CONFIG message to client");
-            }
-            // Handle loading the configuration
-            else if text.starts_with("LOAD_CONFIG:") {
-                let profile_name = text.replace("LOAD_CONFIG:", "");
-                let config_guard = config.lock().await;
-                if let Some(keybindings) = config_guard["profiles"].get(&profile_name) {
-                    let config_str = serde_json::to_string(keybindings).expect("Failed to serialize config");
-                    let mut write_guard = write.lock().await;
-                    let _ = write_guard
-                        .send(Message::Text(format!("CONFIG:{}", config_str)))
-                        .await;
-                } else {
-                    let mut write_guard = write.lock().await;
-                    let _ = write_guard
-                        .send(Message::Text(format!(
-                            "ERROR:Profile '{}' does not exist or has no configuration",
-                            profile_name
-                        )))
-                        .await;
-                }
-            }
-            else if text == "GET_CURRENT_PROFILE" {
-                let config_guard = config.lock().await;
-                if let Some(current_profile) = config_guard["currentProfile"].as_str() {
-                    let mut write_guard = write.lock().await;
-                    let _ = write_guard
-                        .send(Message::Text(format!("CURRENT_PROFILE:{}", current_profile)))
-                        .await;
-                } else {
-                    let mut write_guard = write.lock().await;
-                    let _ = write_guard
-                        .send(Message::Text("CURRENT_PROFILE:None".to_string()))
-                        .await;
-                }
-            }
+    let replies = if is_json { vec![reply.to_json(id.as_ref())] } else { reply.to_legacy() };
+    let mut write_guard = write.lock().await;
+    for reply in replies {
+        let _ = write_guard.send(Message::Text(reply)).await;
+    }
+    authenticated
+}
 
-            // Handle saving the last selected profile
-            else if text.starts_with("SAVE_LAST_SELECTED_PROFILE:") {
-                let profile_name = text.replace("SAVE_LAST_SELECTED_PROFILE:", "");
-                let mut config_guard = config.lock().await;
-                
-                // Save the last selected profile
-                config_guard["lastSelectedProfile"] = serde_json::Value::String(profile_name.clone());
-                
-                // Also update currentProfile if it exists
-                if config_guard["profiles"][&profile_name].is_object() {
-                    config_guard["currentProfile"] = serde_json::Value::String(profile_name.clone());
+// Relays config changes made elsewhere to one client until its socket goes away
+async fn forward_events(
+    mut events: broadcast::Receiver<ServerEvent>,
+    connection_id: u64,
+    speaks_json: Arc<AtomicBool>,
+    write: Arc<Mutex<WebSocketTx>>,
+) {
+    loop {
+        match events.recv().await {
+            Ok(ServerEvent { origin, event }) => {
+                if origin == Some(connection_id) {
+                    continue;
                 }
-                
-                save_config(&config_guard);
-            }
-
-            // Handle requesting the last selected profile
-            else if text == "GET_LAST_SELECTED_PROFILE" {
-                let config_guard = config.lock().await;
-                if let Some(last_profile) = config_guard["lastSelectedProfile"].as_str() {
-                    let mut write_guard = write.lock().await;
-                    let _ = write_guard
-                        .send(Message::Text(format!("LAST_SELECTED_PROFILE:{}", last_profile)))
-                        .await;
+                let messages = if speaks_json.load(Ordering::Relaxed) {
+                    vec![event.to_json()]
                 } else {
-                    let mut write_guard = write.lock().await;
-                    let _ = write_guard
-                        .send(Message::Text("LAST_SELECTED_PROFILE:None".to_string()))
-                        .await;
-                }
-            } 
-            // Handle getting profiles
-            else if text == "GET_PROFILES" {
-                let config_guard = config.lock().await;
-                let profiles = config_guard["profiles"]
-                    .as_object()
-                    .unwrap_or(&serde_json::Map::new())
-                    .keys()
-                    .cloned()
-                    .collect::<Vec<_>>();
-                let profiles_str =
-                    serde_json::to_string(&profiles).expect("Failed to serialize profiles");
+                    event.to_legacy()
+                };
 
                 let mut write_guard = write.lock().await;
-                let _ = write_guard
-                    .send(Message::Text(format!("PROFILES:{}", profiles_str)))
-                    .await;
-            }
-            else if text.starts_with("SWITCH_PROFILE:") {
-                let profile_name = text.replace("SWITCH_PROFILE:", "");
-                let mut config_guard = config.lock().await;
-            
-                if config_guard["profiles"][&profile_name].is_object() {
-                    config_guard["currentProfile"] = serde_json::Value::String(profile_name.clone());
-                    config_guard["lastSelectedProfile"] = serde_json::Value::String(profile_name.clone());
-            
-                    save_config(&config_guard);
-            
-                    let mut write_guard = write.lock().await;
-                    
-                    // Send PROFILE_SWITCHED message first
-                    let _ = write_guard
-                        .send(Message::Text(format!("PROFILE_SWITCHED:{}", profile_name)))
-                        .await;
-            
-                    // Then send the CONFIG message
-                    let keybindings = config_guard["profiles"][&profile_name].clone();
-                    let config_str = serde_json::to_string(&keybindings).expect("Failed to serialize config");
-                    let _ = write_guard
-                        .send(Message::Text(format!("CONFIG:{}", config_str)))
-                        .await;
-                } else {
-                    let mut write_guard = write.lock().await;
-                    let _ = write_guard
-                        .send(Message::Text(format!(
-                            "ERROR:Profile '{}' does not exist",
-                            profile_name
-                        )))
-                        .await;
+                for message in messages {
+                    if write_guard.send(Message::Text(message)).await.is_err() {
+                        return;
+                    }
                 }
             }
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                eprintln!("Client fell behind, {} events dropped", skipped);
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
         }
     }
 }
 
 
-async fn handle_key_events(
-    device_state: Arc<DeviceState>,
-    last_keys: Arc<Mutex<HashSet<Keycode>>>,
+// Sends every combo from the shared keyboard listener to one client until its socket goes away
+async fn forward_combos(
+    mut combos: broadcast::Receiver<input::InputEvent>,
+    armed: bool,
+    speaks_json: Arc<AtomicBool>,
     write: Arc<Mutex<WebSocketTx>>,
-    tx: broadcast::Sender<String>,
-    _config: Arc<Mutex<Value>>, // Config is not needed here for now
 ) {
+    // Panels show whether triggers are armed from the moment they connect
+    let message = events::input_message(&input::InputEvent::Armed(armed), speaks_json.load(Ordering::Relaxed));
+    if write.lock().await.send(Message::Text(message)).await.is_err() {
+        return;
+    }
     loop {
-        let keys: HashSet<Keycode> = device_state.get_keys().into_iter().collect();
-        let mut last_keys_guard = last_keys.lock().await;
-
-        if !keys.is_empty() {
-            for key in &keys {
-                last_keys_guard.insert(key.clone());
-            }
-        }
-
-        if keys.is_empty() && !last_keys_guard.is_empty() {
-            let combo = last_keys_guard
-                .iter()
-                .map(|k| map_keycode(k))
-                .collect::<Vec<String>>()
-                .join("+");
-
-            if !combo.is_empty() {
-                let normalized_combo = normalize_key_combination(&combo);
-
-                println!("Detected key combination: {}", normalized_combo);
-
-                // Always send the combo to the client
-                let _ = tx.send(format!("COMBO:{}", normalized_combo));
-
+        match combos.recv().await {
+            Ok(combo) => {
+                let message = events::input_message(&combo, speaks_json.load(Ordering::Relaxed));
                 let mut write_guard = write.lock().await;
-                let _ = write_guard
-                    .send(Message::Text(format!("COMBO:{}", normalized_combo)))
-                    .await;
+                if write_guard.send(Message::Text(message)).await.is_err() {
+                    break;
+                }
             }
-
-            last_keys_guard.clear();
-            tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                eprintln!("Client fell behind, {} combos dropped", skipped);
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
         }
-
-        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
     }
 }
 
-fn get_config_path() -> PathBuf {
-    let config_path = if cfg!(target_os = "windows") {
-        let appdata_dir = env::var("APPDATA").expect("Failed to get APPDATA environment variable");
-        PathBuf::from(appdata_dir).join("AudioImporter").join("config.json")
-    } else {
-        let home_dir = env::var("HOME").expect("Failed to get HOME environment variable");
-        PathBuf::from(home_dir).join("Library").join("Application Support").join("AudioImporter").join("config.json")
-    };
-
-    // Ensure the directory exists
-    fs::create_dir_all(config_path.parent().unwrap()).expect("Failed to create config directory");
-
-    config_path
-}
 
+async fn handle_incoming_messages(
+    mut read: WebSocketRx,
+    state: AppState,
+    write: Arc<Mutex<WebSocketTx>>,
+    session: &mut Session,
+    speaks_json: Arc<AtomicBool>,
+) {
+    while let Some(message) = read.next().await {
+        let message = match message {
+            Ok(message) => message,
+            Err(e) => {
+                eprintln!("Failed to read message: {}", e);
+                break;
+            }
+        };
 
+        if let Message::Text(text) = message {
+            let is_json = protocol::is_json(&text);
+            if is_json {
+                speaks_json.store(true, Ordering::Relaxed);
+            }
 
-fn load_config() -> Value {
-    let config_path = get_config_path();
-    let config_data = fs::read_to_string(&config_path).unwrap_or_else(|_| "{}".to_string());
+            let (id, reply) = match protocol::parse_message(&text) {
+                Ok((id, command)) => (id, commands::handle_command(command, &state, session).await),
+                Err((id, reply)) => (id, reply),
+            };
+            if let Reply::Error { message, .. } = &reply {
+                eprintln!("Request failed: {}", message);
+            }
 
-    let mut config: Value =
-        serde_json::from_str(&config_data).expect("Failed to parse config JSON");
+            // Answer in the same dialect the client spoke
+            let replies = if is_json {
+                vec![reply.to_json(id.as_ref())]
+            } else {
+                reply.to_legacy()
+            };
+            let mut write_guard = write.lock().await;
+            for reply in replies {
+                let _ = write_guard.send(Message::Text(reply)).await;
+            }
 
-    // Ensure the config has profiles and currentProfile
-    if !config["profiles"].is_object() {
-        config["profiles"] = serde_json::json!({}); // Initialize as empty object
-    }
-    if !config["currentProfile"].is_string() {
-        if let Some((first_profile, _)) = config["profiles"].as_object().unwrap().iter().next() {
-            config["currentProfile"] = serde_json::Value::String(first_profile.clone());
-        } else {
-            config["currentProfile"] = Value::Null; // Set to null if no profiles exist
+            // Don't keep talking to a client that can't understand us; the close code tells it not to retry
+            if let Reply::Error { code: ErrorCode::IncompatibleProtocol, .. } = reply {
+                let close = CloseFrame { code: CloseCode::Protocol, reason: "Incompatible protocol".into() };
+                let _ = write_guard.send(Message::Close(Some(close))).await;
+                break;
+            }
         }
     }
-    if !config["lastSelectedProfile"].is_string() {
-        config["lastSelectedProfile"] = config["currentProfile"].clone();
-    }
-
-    config
-}
-
-fn save_config(config: &Value) {
-    let config_path = get_config_path();
-    let config_data =
-        serde_json::to_string_pretty(config).expect("Failed to serialize config JSON");
-
-    fs::write(config_path, config_data).expect("Failed to write config file");
-}
-
-fn map_keycode(key: &Keycode) -> String {
-    match format!("{:?}", key).as_str() {
-        // Number keys
-        "Key1" => "1".to_string(),
-        "Key2" => "2".to_string(),
-        "Key3" => "3".to_string(),
-        "Key4" => "4".to_string(),
-        "Key5" => "5".to_string(),
-        "Key6" => "6".to_string(),
-        "Key7" => "7".to_string(),
-        "Key8" => "8".to_string(),
-        "Key9" => "9".to_string(),
-        "Key0" => "0".to_string(),
-        
-        // Numpad keys
-        "Numpad0" => "Numpad0".to_string(),
-        "Numpad1" => "Numpad1".to_string(),
-        "Numpad2" => "Numpad2".to_string(),
-        "Numpad3" => "Numpad3".to_string(),
-        "Numpad4" => "Numpad4".to_string(),
-        "Numpad5" => "Numpad5".to_string(),
-        "Numpad6" => "Numpad6".to_string(),
-        "Numpad7" => "Numpad7".to_string(),
-        "Numpad8" => "Numpad8".to_string(),
-        "Numpad9" => "Numpad9".to_string(),
-        
-        // Function keys
-        "F1" => "F1".to_string(),
-        "F2" => "F2".to_string(),
-        "F3" => "F3".to_string(),
-        "F4" => "F4".to_string(),
-        "F5" => "F5".to_string(),
-        "F6" => "F6".to_string(),
-        "F7" => "F7".to_string(),
-        "F8" => "F8".to_string(),
-        "F9" => "F9".to_string(),
-        "F10" => "F10".to_string(),
-        "F11" => "F11".to_string(),
-        "F12" => "F12".to_string(),
-        
-        // Numpad special keys
-        "NumpadSlash" => "NumpadDivide".to_string(),
-        "NumpadAsterisk" => "NumpadMultiply".to_string(),
-        "NumpadMinus" => "NumpadSubtract".to_string(),
-        "NumpadPlus" => "NumpadAdd".to_string(),
-        "NumpadEnter" => "NumpadEnter".to_string(),
-        "NumpadDot" => "NumpadDecimal".to_string(),
-        
-        // Modifier keys (Windows and macOS)
-        "ControlLeft" => "Ctrl".to_string(),
-        "ControlRight" => "Ctrl".to_string(),
-        "AltLeft" => "Alt".to_string(),
-        "AltRight" => "Alt".to_string(),
-        "ShiftLeft" => "Shift".to_string(),
-        "ShiftRight" => "Shift".to_string(),
-        "MetaLeft" => if cfg!(target_os = "macos") { "Cmd".to_string() } else { "Win".to_string() },
-        "MetaRight" => if cfg!(target_os = "macos") { "Cmd".to_string() } else { "Win".to_string() },
-        
-        // Other common keys
-        "Space" => "Space".to_string(),
-        "Return" => "Enter".to_string(),
-        "Escape" => "Esc".to_string(),
-        "Tab" => "Tab".to_string(),
-        "CapsLock" => "CapsLock".to_string(),
-        
-        // Default case
-        _ => format!("{:?}", key),
-    }
 }
-
-fn normalize_key_combination(key_combination: &str) -> String {
-    let key_map: HashMap<&str, &str> = [
-        ("LAlt", "Alt"),
-        ("RAlt", "Alt"),
-        ("LControl", "Ctrl"),
-        ("RControl", "Ctrl"),
-        ("ControlLeft", "Ctrl"),
-        ("ControlRight", "Ctrl"),
-        ("ShiftLeft", "Shift"),
-        ("ShiftRight", "Shift"),
-        ("MetaLeft", if cfg!(target_os = "macos") { "Cmd" } else { "Win" }),
-        ("MetaRight", if cfg!(target_os = "macos") { "Cmd" } else { "Win" }),
-        ("Numpad1", "1"),
-        ("Numpad2", "2"),
-        ("Numpad3", "3"),
-        ("Numpad4", "4"),
-        ("Numpad5", "5"),
-        ("Numpad6", "6"),
-        ("Numpad7", "7"),
-        ("Numpad8", "8"),
-        ("Numpad9", "9"),
-        ("Numpad0", "0"),
-    ].iter().cloned().collect();
-
-    let priority = if cfg!(target_os = "macos") {
-        vec!["Cmd", "Ctrl", "Alt", "Shift"]
-    } else {
-        vec!["Ctrl", "Alt", "Shift", "Win"]
-    };
-
-    let mut mapped_keys: Vec<String> = key_combination
-        .split('+')
-        .map(|key| key_map.get(key).unwrap_or(&key).to_string())
-        .collect();
-
-    mapped_keys.sort_by(|a, b| {
-        let a_index = priority.iter().position(|x| x.eq_ignore_ascii_case(a)).unwrap_or(usize::MAX);
-        let b_index = priority.iter().position(|x| x.eq_ignore_ascii_case(b)).unwrap_or(usize::MAX);
-
-        if a_index == b_index {
-            a.cmp(b)
-        } else {
-            a_index.cmp(&b_index)
-        }
-    });
-
-    mapped_keys.dedup(); // Remove duplicates
-    mapped_keys.join("+")
-}
\ No newline at end of file
@@ -0,0 +1,37 @@
+use crate::config::Config;
+use crate::events::ServerEvent;
+use crate::input::InputHub;
+use std::sync::atomic::AtomicUsize;
+use std::sync::Arc;
+use tokio::sync::{broadcast, Mutex};
+
+/// Everything shared between connections. Cheap to clone.
+#[derive(Clone)]
+pub struct AppState {
+    pub config: Arc<Mutex<Config>>,
+    pub events: broadcast::Sender<ServerEvent>,
+    pub input: Arc<InputHub>,
+    /// WebSocket connections currently open.
+    pub connections: Arc<AtomicUsize>,
+    /// The secret clients must present, read once at startup.
+    pub auth_token: Arc<str>,
+}
+
+#[cfg(test)]
+impl AppState {
+    /// State around `config` with no input sources, its current profile's bindings armed, and
+    /// `secret` as the auth token.
+    pub fn for_config(config: Config) -> Self {
+        use crate::foreground::FixedForeground;
+
+        let input = InputHub::start(Vec::new(), &config.input, Box::new(FixedForeground(None)));
+        input.set_bindings(&config);
+        AppState {
+            config: Arc::new(Mutex::new(config)),
+            events: broadcast::channel(16).0,
+            input,
+            connections: Arc::new(AtomicUsize::new(0)),
+            auth_token: Arc::from("secret"),
+        }
+    }
+}
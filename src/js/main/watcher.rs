@@ -0,0 +1,163 @@
+use crate::config::{self, Config};
+use crate::events::{Event, ServerEvent};
+use crate::paths::get_config_path;
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::{broadcast, Mutex};
+use tokio::time::{sleep, Duration};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// Reloads `config.json` when another program edits it and tells every panel with `CONFIG_CHANGED`.
+/// Writes made by `save_config` are recognised and skipped.
+pub async fn watch_config(config: Arc<Mutex<Config>>, tx: broadcast::Sender<ServerEvent>) {
+    let config_path = get_config_path();
+    let mut last_seen = contents_hash(&config_path);
+
+    loop {
+        sleep(POLL_INTERVAL).await;
+        reload_if_changed(&config_path, &mut last_seen, &config, &tx).await;
+    }
+}
+
+/// Compares contents rather than the mtime: FAT and exFAT, which portable installs run from,
+/// only keep it to 2 s, so a second write in the same window would go unnoticed.
+async fn reload_if_changed(
+    config_path: &Path,
+    last_seen: &mut Option<u64>,
+    config: &Mutex<Config>,
+    tx: &broadcast::Sender<ServerEvent>,
+) {
+    if contents_hash(config_path) == *last_seen {
+        return;
+    }
+
+    // The server only saves under this lock, so what is read while holding it can't be
+    // overtaken by one of its saves and swapped in over newer state
+    let mut config_guard = config.lock().await;
+    let Ok(contents) = fs::read_to_string(config_path) else { return };
+    *last_seen = Some(hash(&contents));
+    if config::is_own_write(&contents) {
+        return;
+    }
+
+    match config::parse_external_config(&contents) {
+        Ok(new_config) => {
+            if *config_guard == new_config {
+                return;
+            }
+            *config_guard = new_config;
+
+            let _ = tx.send(ServerEvent { origin: None, event: Event::ConfigChanged(Box::new(config_guard.clone())) });
+            println!("Reloaded config edited outside the server");
+        }
+        // Most likely caught mid-write; the finished write changes the contents again
+        Err(e) => eprintln!("Ignoring external edit to {}: {}", config_path.display(), e),
+    }
+}
+
+fn contents_hash(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok().map(|contents| hash(&contents))
+}
+
+fn hash(contents: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AudioBinding, Config};
+    use std::path::PathBuf;
+
+    /// A config file of its own in the temp directory, removed when dropped.
+    struct TempConfig(PathBuf);
+
+    impl TempConfig {
+        fn new(name: &str) -> Self {
+            TempConfig(std::env::temp_dir().join(format!("audio_importer_watcher_{}_{}.json", name, std::process::id())))
+        }
+
+        /// Writes a config whose only profile binds `Ctrl+1` to `path`, and returns it as parsed.
+        fn write(&self, path: &str) -> Config {
+            let mut config = Config::default();
+            config.upsert_profile("Vlog", [("Ctrl+1".to_string(), AudioBinding::for_file(path))].into());
+            config.normalize();
+            let contents = serde_json::to_string_pretty(&config).unwrap();
+            fs::write(&self.0, &contents).unwrap();
+            config::parse_external_config(&contents).unwrap()
+        }
+    }
+
+    impl Drop for TempConfig {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn changes(rx: &mut broadcast::Receiver<ServerEvent>) -> usize {
+        std::iter::from_fn(|| rx.try_recv().ok()).count()
+    }
+
+    #[tokio::test]
+    async fn external_edit_is_loaded_once() {
+        let file = TempConfig::new("edit");
+        let config = Mutex::new(file.write("/sfx/a.wav"));
+        let mut last_seen = contents_hash(&file.0);
+        let (tx, mut rx) = broadcast::channel(16);
+
+        let edited = file.write("/sfx/b.wav");
+        reload_if_changed(&file.0, &mut last_seen, &config, &tx).await;
+        reload_if_changed(&file.0, &mut last_seen, &config, &tx).await;
+        assert_eq!(*config.lock().await, edited);
+        assert_eq!(changes(&mut rx), 1);
+    }
+
+    #[tokio::test]
+    async fn second_write_with_the_same_mtime_is_noticed() {
+        let file = TempConfig::new("mtime");
+        let config = Mutex::new(file.write("/sfx/a.wav"));
+        let (tx, _rx) = broadcast::channel(16);
+        let first = file.write("/sfx/b.wav");
+        let mut last_seen = None;
+        reload_if_changed(&file.0, &mut last_seen, &config, &tx).await;
+        assert_eq!(*config.lock().await, first);
+
+        // Both writes land in the same timestamp, as within FAT's 2 s resolution
+        let mtime = fs::metadata(&file.0).unwrap().modified().unwrap();
+        let second = file.write("/sfx/c.wav");
+        fs::File::options().write(true).open(&file.0).unwrap().set_modified(mtime).unwrap();
+        assert_eq!(fs::metadata(&file.0).unwrap().modified().unwrap(), mtime);
+
+        reload_if_changed(&file.0, &mut last_seen, &config, &tx).await;
+        assert_eq!(*config.lock().await, second);
+    }
+
+    #[tokio::test]
+    async fn save_landing_before_the_lock_is_not_overwritten() {
+        let file = TempConfig::new("race");
+        let config = Arc::new(Mutex::new(file.write("/sfx/a.wav")));
+        let mut last_seen = contents_hash(&file.0);
+        let (tx, mut rx) = broadcast::channel(16);
+
+        // An external edit the watcher notices while the server is busy saving
+        file.write("/sfx/external.wav");
+        let mut guard = config.lock().await;
+        let watcher = {
+            let (config, tx, path) = (Arc::clone(&config), tx.clone(), file.0.clone());
+            tokio::spawn(async move { reload_if_changed(&path, &mut last_seen, &config, &tx).await })
+        };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let saved = file.write("/sfx/saved.wav");
+        *guard = saved.clone();
+        drop(guard);
+
+        watcher.await.unwrap();
+        assert_eq!(*config.lock().await, saved);
+        assert_eq!(changes(&mut rx), 0);
+    }
+}
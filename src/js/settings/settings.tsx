@@ -1,5 +1,6 @@
 import { useEffect, useRef, useState, useCallback } from 'react';
-import { Config, AudioBinding, ProfileConfig } from './types';
+import { Config, Trigger } from './types';
+import { INCOMPATIBLE_CLOSE_CODE, helloMessage, serverUrl } from '../lib/utils/server';
 import path from 'path';
 
 declare global {
@@ -41,12 +42,13 @@ const Settings: React.FC = () => {
         }
 
         try {
-            socketRef.current = new WebSocket('ws://localhost:7878');
+            socketRef.current = new WebSocket(serverUrl());
             console.log("WebSocket connection object created");
 
             socketRef.current.onopen = () => {
                 console.log("WebSocket connected to Rust server");
                 appendToDebugLog("Connected to Rust server");
+                socketRef.current?.send(helloMessage('SETTINGS_PANEL'));
                 setIsConnected(true);
                 loadConfig();
 
@@ -64,18 +66,22 @@ const Settings: React.FC = () => {
                 appendToDebugLog(`Received message: ${data}`);
 
                 if (typeof data === 'string') {
-                    if (data.startsWith('CONFIG:')) {
+                    if (data.startsWith('WELCOME:')) {
+                        const [serverVersion, protocol] = data.replace('WELCOME:', '').split(':');
+                        appendToDebugLog(`Server ${serverVersion} speaks protocol ${protocol}`);
+                    } else if (data.startsWith('ERROR:')) {
+                        appendToDebugLog(`Error: ${data.replace('ERROR:', '')}`);
+                    } else if (data.startsWith('CONFIG:')) {
                         const configData = JSON.parse(data.replace("CONFIG:", ""));
                         setConfig(configData);
                         configRef.current = configData;
                         appendToDebugLog('Config loaded successfully from server');
                         appendToDebugLog(`Updated config: ${JSON.stringify(configData, null, 2)}`);
+                    } else if (data.startsWith("TRIGGER:")) {
+                        handleTrigger(JSON.parse(data.replace("TRIGGER:", "")));
                     } else if (data.startsWith("COMBO:")) {
-                        const combo = data.replace("COMBO:", "");
-                        appendToDebugLog(`Combo received: ${combo}`);
-                        handleCombo(combo).catch(error => {
-                            appendToDebugLog(`Error in handleCombo: ${error}`);
-                        });
+                        // Unbound combos only matter to the main panel when it captures a new binding
+                        appendToDebugLog(`Combo received: ${data.replace("COMBO:", "")}`);
                     } else if (data.startsWith("LAST_SELECTED_PROFILE:")) {
                         const profile = data.replace("LAST_SELECTED_PROFILE:", "");
                         setLastSelectedProfile(profile);
@@ -94,6 +100,10 @@ const Settings: React.FC = () => {
                 console.log(`WebSocket closed: ${event.reason}`);
                 appendToDebugLog(`WebSocket closed: ${event.reason}`);
                 setIsConnected(false);
+                if (event.code === INCOMPATIBLE_CLOSE_CODE) {
+                    appendToDebugLog("This panel and the server are not compatible; update one of them");
+                    return;
+                }
                 retryConnection();
             };
         } catch (error) {
@@ -131,77 +141,29 @@ const Settings: React.FC = () => {
         sendMessage('GET_LAST_SELECTED_PROFILE');
     }, [sendMessage]);
 
-    // Handle incoming key combos
-    const handleCombo = async (combo: string) => {
-        appendToDebugLog(`Handling combo: ${combo}`);
-
-        let latestConfig: Config;
-        let profile: string | null;
-
-        try {
-            [latestConfig, profile] = await fetchLatestConfig();
-            appendToDebugLog(`Fetched latest config: ${JSON.stringify(latestConfig, null, 2)}`);
-            appendToDebugLog(`Current profile: ${profile}`);
-        } catch (error) {
-            appendToDebugLog(`Error fetching latest config: ${error}. Using current state.`);
-            latestConfig = configRef.current;
-            profile = lastSelectedProfile;
-            appendToDebugLog(`Using config: ${JSON.stringify(latestConfig, null, 2)}`);
-            appendToDebugLog(`Using profile: ${profile}`);
-        }
-
-        if (!profile) {
-            appendToDebugLog(`No profile selected, skipping combo execution`);
-            return;
-        }
-
-        if (!latestConfig.profiles || !latestConfig.profiles[profile]) {
-            appendToDebugLog(`Selected profile ${profile} not found in config, skipping combo execution`);
-            return;
-        }
-
-        const profileConfig = latestConfig.profiles[profile];
-
-        if (Object.keys(profileConfig).length === 0) {
-            appendToDebugLog(`Config for profile ${profile} is empty, no bindings to process`);
-            return;
-        }
-
-        const normalizedCombo = normalizeKeyCombination(combo);
-        appendToDebugLog(`Normalized combo: ${normalizedCombo}`);
-
-        const normalizedConfig: ProfileConfig = Object.fromEntries(
-            Object.entries(profileConfig).map(([key, value]) => [
-                normalizeKeyCombination(key),
-                value
-            ])
-        );
-
-        appendToDebugLog(`Normalized config for profile ${profile}: ${JSON.stringify(normalizedConfig, null, 2)}`);
-
-        if (normalizedConfig[normalizedCombo]) {
-            const binding: AudioBinding = normalizedConfig[normalizedCombo];
-            appendToDebugLog(`Found binding for combo: ${normalizedCombo}`);
-
-            if (binding.path) {
-                appendToDebugLog(`Executing script for path: ${binding.path}, track: ${binding.track}, volume: ${binding.volume}dB, and pitch: ${binding.pitch} semitones`);
-                executePremiereProScript(
-                    binding.path,
-                    parseInt(binding.track.replace('A', ''), 10),
-                    binding.volume,
-                    binding.pitch || 0 // Provide a default value of 0 if pitch is undefined
-                );
-            } else {
-                appendToDebugLog(`No path specified for combo: ${normalizedCombo}`);
-            }
+    // The server resolves combos against the current profile (gestures included) and only
+    // sends a trigger for bound ones
+    const handleTrigger = (trigger: Trigger) => {
+        const { binding, combo, gesture, profile } = trigger;
+        appendToDebugLog(`Trigger for ${combo} (${gesture}) in profile ${profile}`);
+
+        if (binding.path) {
+            appendToDebugLog(`Executing script for path: ${binding.path}, track: ${binding.track}, volume: ${binding.volume}dB, pitch: ${binding.pitch} semitones, and importInMiddle: ${binding.importInMiddle}`);
+            executePremiereProScript(
+                binding.path,
+                parseInt(binding.track.replace('A', ''), 10),
+                binding.volume,
+                binding.pitch || 0, // Provide a default value of 0 if pitch is undefined
+                binding.importInMiddle === true
+            );
         } else {
-            appendToDebugLog(`Combo ${normalizedCombo} not found in config for profile ${profile}. Skipping execution.`);
+            appendToDebugLog(`No path specified for combo: ${combo}`);
         }
     };
 
     // Execute Premiere Pro JSX script
-    const executePremiereProScript = (filePath: string, track: number, volume: number, pitch: number) => {
-        appendToDebugLog(`Executing script with parameters: filePath=${filePath}, track=${track}, volume=${volume}, pitch=${pitch}`);
+    const executePremiereProScript = (filePath: string, track: number, volume: number, pitch: number, importInMiddle: boolean) => {
+        appendToDebugLog(`Executing script with parameters: filePath=${filePath}, track=${track}, volume=${volume}, pitch=${pitch}, importInMiddle=${importInMiddle}`);
 
         const jsxRelativePath = './jsx/importAudio.jsx';
         const jsxFullPath = path.resolve(__dirname, jsxRelativePath);
@@ -219,7 +181,7 @@ const Settings: React.FC = () => {
                 var jsxFile = new File("${formattedJsxPath}");
                 if (jsxFile.exists) {
                     $.evalFile(jsxFile);
-                    var result = importAudioToTrack("${formattedFilePath}", ${track}, ${volume}, ${pitch}, true);
+                    var result = importAudioToTrack("${formattedFilePath}", ${track}, ${volume}, ${pitch}, true, ${importInMiddle});
                     result; // Return the result from the function
                 } else {
                     throw new Error("JSX file not found at: " + jsxFile.fsName);
@@ -256,96 +218,6 @@ const Settings: React.FC = () => {
         }
     };
 
-    const normalizeKeyCombination = (keyCombination: string): string => {
-        const keyMap: { [key: string]: string } = {
-            'lalt': 'alt',
-            'ralt': 'alt',
-            'lcontrol': 'ctrl',
-            'rcontrol': 'ctrl',
-            'controlleft': 'ctrl',
-            'controlright': 'ctrl',
-            'shiftleft': 'shift',
-            'shiftright': 'shift',
-            'metaleft': navigator.platform.toUpperCase().indexOf('MAC') >= 0 ? 'cmd' : 'win',
-            'metaright': navigator.platform.toUpperCase().indexOf('MAC') >= 0 ? 'cmd' : 'win',
-            'numpad1': '1',
-            'numpad2': '2',
-            'numpad3': '3',
-            'numpad4': '4',
-            'numpad5': '5',
-            'numpad6': '6',
-            'numpad7': '7',
-            'numpad8': '8',
-            'numpad9': '9',
-            'numpad0': '0',
-        };
-
-        const isMac = navigator.platform.toUpperCase().indexOf('MAC') >= 0;
-        const priority = isMac
-            ? ['cmd', 'ctrl', 'alt', 'shift']
-            : ['ctrl', 'alt', 'shift', 'win'];
-
-        const mappedKeys = keyCombination.toLowerCase().split('+')
-            .map(key => keyMap[key] || key)
-            .filter((key, index, self) => self.indexOf(key) === index); // Remove duplicates
-
-        const sortedKeys = mappedKeys.sort((a, b) => {
-            const aIndex = priority.indexOf(a);
-            const bIndex = priority.indexOf(b);
-            if (aIndex === -1 && bIndex === -1) return a.localeCompare(b);
-            if (aIndex === -1) return 1;
-            if (bIndex === -1) return -1;
-            return aIndex - bIndex;
-        });
-
-        return sortedKeys.map(key => key.charAt(0).toUpperCase() + key.slice(1)).join('+');
-    };
-
-    // Fetch the latest config and profile from the WebSocket
-    const fetchLatestConfig = (): Promise<[Config, string | null]> => {
-        return new Promise((resolve, reject) => {
-            if (socketRef.current && socketRef.current.readyState === WebSocket.OPEN) {
-                let configData: Config | null = null;
-                let lastProfile: string | null = null;
-
-                const messageHandler = (event: MessageEvent) => {
-                    const data = event.data;
-                    if (typeof data === 'string') {
-                        if (data.startsWith("CONFIG:")) {
-                            configData = JSON.parse(data.replace("CONFIG:", ""));
-                            appendToDebugLog(`Received CONFIG: ${JSON.stringify(configData, null, 2)}`);
-                        } else if (data.startsWith("LAST_SELECTED_PROFILE:")) {
-                            lastProfile = data.replace("LAST_SELECTED_PROFILE:", "").trim();
-                            appendToDebugLog(`Received LAST_SELECTED_PROFILE: ${lastProfile}`);
-                        }
-
-                        if (configData !== null && lastProfile !== null) {
-                            socketRef.current?.removeEventListener('message', messageHandler);
-                            configRef.current = configData;
-                            setConfig(configData);
-                            setLastSelectedProfile(lastProfile);
-                            resolve([configData, lastProfile]);
-                        }
-                    }
-                };
-
-                socketRef.current.addEventListener('message', messageHandler);
-                socketRef.current.send('LOAD_CONFIG');
-                appendToDebugLog("Sent LOAD_CONFIG message");
-                socketRef.current.send('GET_LAST_SELECTED_PROFILE');
-                appendToDebugLog("Sent GET_LAST_SELECTED_PROFILE message");
-
-                setTimeout(() => {
-                    socketRef.current?.removeEventListener('message', messageHandler);
-                    appendToDebugLog("Timeout while fetching config and profile");
-                    reject(new Error("Timeout while fetching config and profile"));
-                }, 5000); // 5 second timeout
-            } else {
-                reject(new Error("WebSocket is not connected"));
-            }
-        });
-    };
-
     const appendToDebugLog = (message: string) => {
         console.log('Debug log:', message);
         setDebugLog(prevLog => [...prevLog, message]);
@@ -4,6 +4,27 @@ export interface AudioBinding {
   track: string;
   path: string;
   importInMiddle: boolean;
+  /** Set until a file is picked; the server keeps a draft without a path but never triggers it. */
+  draft?: boolean;
+  /** Played instead when the combo is held down. */
+  hold?: AudioBinding;
+  /** Played instead when the combo is tapped twice quickly. */
+  doubleTap?: AudioBinding;
+}
+
+export type Gesture = "tap" | "hold" | "doubleTap";
+
+export interface ProfileSettings {
+  gestures?: {
+    holdMs?: number;
+    doubleTapMs?: number;
+  };
+  /** Change with SET_NORMALIZATION, which also migrates the profile's combos. */
+  normalization?: {
+    distinguishSides?: boolean;
+    separateNumpad?: boolean;
+    modifierOrder?: string[];
+  };
 }
 
 export interface ProfileConfig {
@@ -11,9 +32,80 @@ export interface ProfileConfig {
 }
 
 export interface Config {
+  schemaVersion?: number;
   currentProfile: string;
   lastSelectedProfile: string;
   profiles: {
     [profileName: string]: ProfileConfig;
   };
-}
\ No newline at end of file
+  profileOrder?: string[];
+  input?: InputSettings;
+  profileSettings?: {
+    [profileName: string]: ProfileSettings;
+  };
+  premiereShortcuts?: string;
+  osc?: OscSettings;
+  http?: HttpSettings;
+  server?: ServerSettings;
+}
+
+// OSC listener for control surfaces such as TouchOSC; read when the server starts
+export interface OscSettings {
+  enabled?: boolean;
+  bind?: string;
+}
+
+// The WebSocket listener the panels connect to; the address is read when the server starts
+export interface ServerSettings {
+  host?: string;
+  port?: number;
+  // How many following ports to try when `port` is taken; 0 to give up instead
+  portFallbacks?: number;
+  // Origins a browser-based client may connect from; clients without an Origin always can.
+  // Defaults to ["file://"]; add "http://localhost:3000" and "http://localhost:5000" while
+  // running the panels from the dev or preview server
+  allowedOrigins?: string[];
+}
+
+// HTTP API for scripts and Stream Deck buttons; read when the server starts
+export interface HttpSettings {
+  enabled?: boolean;
+  bind?: string;
+}
+
+export interface InputSettings {
+  backend?: "rdev" | "deviceQuery";
+  fireOnPress?: boolean;
+  sequenceTimeoutMs?: number;
+  keyboardLayout?: KeyboardLayout;
+  armCombo?: string;
+  triggerApps?: string[];
+  midi?: MidiSettings;
+}
+
+export interface MidiSettings {
+  enabled?: boolean;
+  // Only listen to input ports whose name contains this
+  port?: string;
+  virtualPort?: boolean;
+}
+
+export type KeyboardLayout = "qwerty" | "azerty" | "qwertz";
+
+// A bound combo resolved by the server, with the binding to play
+export interface Trigger {
+  profile: string;
+  combo: string;
+  label: string;
+  gesture: Gesture;
+  binding: AudioBinding;
+}
+
+// A binding that would also run a Premiere command, an OS shortcut or another binding; a clash
+// between two bindings is reported once, under the key that sorts first
+export interface Conflict {
+  combo: string;
+  stroke: string;
+  kind: "premiere" | "system" | "binding";
+  with: string;
+}
@@ -1,4 +1,5 @@
-function importAudioToTrack(filePath, initialTrackIndex, volume, pitch, debugMode) {
+// importInMiddle centres the clip on the playhead instead of starting it there
+function importAudioToTrack(filePath, initialTrackIndex, volume, pitch, debugMode, importInMiddle) {
     var debugLog = "Debug Log:\n";
 
     function addDebugMessage(message) {
@@ -81,6 +82,13 @@ function importAudioToTrack(filePath, initialTrackIndex, volume, pitch, debugMod
         }
         addDebugMessage("Debug 9: Player position: " + time.seconds + " seconds");
 
+        if (importInMiddle) {
+            var centred = new Time();
+            centred.seconds = Math.max(0, time.seconds - audioDuration / 2);
+            time = centred;
+            addDebugMessage("Debug 9a: Centred on the playhead, starting at " + time.seconds + " seconds");
+        }
+
         initialTrackIndex = parseInt(initialTrackIndex);
         if (isNaN(initialTrackIndex) || initialTrackIndex < 1) {
             initialTrackIndex = 1;